@@ -0,0 +1,653 @@
+//! Batch sending of messages through the Microsoft Graph API JSON `$batch` endpoint.
+//!
+//! See <https://learn.microsoft.com/en-us/graph/json-batching>
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_mailer_core::mail_send::smtp::message::Message;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "tracing")]
+use tracing::{info, instrument};
+
+use crate::draft::MAX_SEND_MAIL_SIZE;
+use crate::message::{self, GraphMessage, SendMailRequest};
+use crate::{
+    graph, OutlookGraphError, OutlookMailer, OutlookMailerError, OutlookSendOptions,
+    OutlookSendReceipt,
+};
+
+/// Largest number of requests accepted by the `$batch` endpoint in a single batch.
+const MAX_BATCH_REQUESTS: usize = 20;
+
+/// Largest number of concurrent requests to a single mailbox allowed by Outlook.
+///
+/// The sub-requests of a `$batch` request are executed concurrently,
+/// so any further sub-requests to the same mailbox would be throttled.
+///
+/// See <https://learn.microsoft.com/en-us/graph/throttling-limits#outlook-service-limits>
+const MAX_MAILBOX_REQUESTS_PER_BATCH: usize = 4;
+
+impl OutlookMailer {
+    /// Send multiple prepared MIME messages via the Microsoft Graph API, grouping them into `$batch` requests.
+    ///
+    /// Messages are converted into JSON messages and sent in batches of up to 20 messages,
+    /// with each batch limited to the `sendMail` request size limit.
+    /// As Outlook limits concurrent requests per mailbox, each batch holds up to 4 messages per sending mailbox.
+    /// The conversion is lossy, see [JSON message conversion](crate#json-message-conversion).
+    /// Messages exceeding the size limit on their own are sent individually through a draft message.
+    /// Sub-requests throttled by the Microsoft Graph API are retried within the configured throttling retry budget.
+    ///
+    /// Returns one result per message, in the order of the input messages.
+    /// Each [`OutlookSendReceipt`] holds the `request-id` and `client-request-id` of the `$batch` request
+    /// which sent the message. Unless set through [`OutlookSendOptions::client_request_id`],
+    /// a client request id is generated per `$batch` request.
+    ///
+    /// # Errors
+    ///
+    /// Each result holds an [`OutlookMailerError::ParseMessage`] error if the MIME message cannot be parsed.
+    ///
+    /// Each result holds an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// to the message's sub-request with a non-success HTTP status code.
+    ///
+    /// Each result holds an [`OutlookMailerError::Batch`] error if the `$batch` request carrying the message failed,
+    /// wrapping the error shared by all messages of the batch.
+    ///
+    /// Each result holds an [`OutlookMailerError::MissingBatchResponse`] error if the `$batch` response
+    /// holds no sub-response for the message.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, messages)))]
+    pub async fn send_mail_batch(
+        &self,
+        messages: impl IntoIterator<Item = Message<'_>>,
+        options: &OutlookSendOptions,
+    ) -> Vec<Result<OutlookSendReceipt, OutlookMailerError>> {
+        let save_to_sent_items = options
+            .save_to_sent_items
+            .unwrap_or(self.save_to_sent_items);
+
+        let mut results = Vec::new();
+        let mut batches = Vec::new();
+
+        for (index, message) in messages.into_iter().enumerate() {
+            // Overwritten with the message's outcome, unless the `$batch` response misses the message.
+            results.push(Err(OutlookMailerError::MissingBatchResponse));
+
+            let mailbox_path = options
+                .mailbox
+                .as_ref()
+                .unwrap_or(&self.mailbox)
+                .path(&message);
+
+            let Some(graph_message) = GraphMessage::parse(&message) else {
+                results[index] = Err(OutlookMailerError::ParseMessage);
+                continue;
+            };
+
+            let size = graph_message.to_send_mail_json(save_to_sent_items).len();
+
+            if size > MAX_SEND_MAIL_SIZE {
+                // Too large for a batch, and for the `sendMail` endpoint. Send through a draft message.
+                let client_request_id = options
+                    .client_request_id
+                    .clone()
+                    .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+                results[index] = self
+                    .send_graph_message(
+                        &mailbox_path,
+                        graph_message,
+                        save_to_sent_items,
+                        &client_request_id,
+                    )
+                    .await
+                    .map(|receipt| OutlookSendReceipt {
+                        internet_message_id: message::internet_message_id(&message),
+                        ..receipt
+                    });
+                continue;
+            }
+
+            let batch_message = BatchMessage {
+                index,
+                internet_message_id: message::internet_message_id(&message),
+                mailbox_path,
+                graph_message,
+            };
+
+            add_to_batches(&mut batches, batch_message, size);
+        }
+
+        for batch in &batches {
+            #[cfg(feature = "tracing")]
+            info!(
+                messages = batch.messages.len(),
+                "Sending Outlook mail batch..."
+            );
+
+            let client_request_id = options
+                .client_request_id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+            self.send_batch(
+                &batch.messages,
+                save_to_sent_items,
+                &client_request_id,
+                &mut results,
+            )
+            .await;
+        }
+
+        results
+    }
+
+    /// Send a single `$batch` request, retrying throttled sub-requests,
+    /// and record the outcome of each message in `results`.
+    async fn send_batch(
+        &self,
+        batch: &[BatchMessage],
+        save_to_sent_items: bool,
+        client_request_id: &str,
+        results: &mut [Result<OutlookSendReceipt, OutlookMailerError>],
+    ) {
+        let mut pending: Vec<&BatchMessage> = batch.iter().collect();
+        let mut throttle_retries = 0;
+
+        while !pending.is_empty() {
+            let request = BatchRequest::send_mail(&pending, save_to_sent_items);
+
+            let (receipt, response) = match self
+                .send_graph_request(Some(client_request_id), |http_client| {
+                    http_client
+                        .post(format!("{graph_url}/$batch", graph_url = self.graph_url))
+                        .json(&request)
+                })
+                .await
+                .and_then(|response| {
+                    serde_json::from_slice::<BatchResponse>(&response.body)
+                        .map(|body| (OutlookSendReceipt::from_response(&response), body))
+                        .map_err(OutlookMailerError::ParseResponse)
+                }) {
+                Ok(response) => response,
+                Err(error) => {
+                    let error = Arc::new(error);
+                    for message in pending {
+                        results[message.index] = Err(OutlookMailerError::Batch(error.clone()));
+                    }
+                    return;
+                }
+            };
+
+            let can_retry = throttle_retries < self.max_throttle_retries;
+            let (throttled, retry_after) = record_responses(
+                &pending,
+                response.responses,
+                &receipt,
+                can_retry.then_some(throttle_retries),
+                results,
+            );
+
+            if throttled.is_empty() || retry_after > self.max_retry_after {
+                return;
+            }
+
+            #[cfg(feature = "tracing")]
+            info!(
+                ?retry_after,
+                messages = throttled.len(),
+                "Microsoft Graph API throttled batched messages; retrying..."
+            );
+
+            throttle_retries += 1;
+            tokio::time::sleep(retry_after).await;
+            pending = throttled;
+        }
+    }
+}
+
+/// Add a message of the given JSON `sendMail` request size to the first batch with room for it,
+/// or start a new batch if every batch is full, would exceed the `sendMail` request size limit,
+/// or already holds the maximum number of messages from the message's mailbox.
+fn add_to_batches(batches: &mut Vec<Batch>, message: BatchMessage, size: usize) {
+    let has_room = |batch: &Batch| {
+        batch.messages.len() < MAX_BATCH_REQUESTS
+            && batch.size + size <= MAX_SEND_MAIL_SIZE
+            && batch
+                .messages
+                .iter()
+                .filter(|batched| batched.mailbox_path == message.mailbox_path)
+                .count()
+                < MAX_MAILBOX_REQUESTS_PER_BATCH
+    };
+
+    match batches.iter_mut().find(|batch| has_room(batch)) {
+        Some(batch) => {
+            batch.size += size;
+            batch.messages.push(message);
+        }
+        None => batches.push(Batch {
+            messages: vec![message],
+            size,
+        }),
+    }
+}
+
+/// Record the outcome of each pending message of a `$batch` request in `results`.
+///
+/// Messages without a sub-response in the `$batch` response are recorded as
+/// [`OutlookMailerError::MissingBatchResponse`] errors.
+///
+/// If `throttle_retries` is given, throttled messages are returned to be retried,
+/// along with the longest delay suggested by their `Retry-After` headers,
+/// falling back to exponential backoff by the number of previous retries.
+fn record_responses<'a>(
+    pending: &[&'a BatchMessage],
+    responses: Vec<BatchResponseItem>,
+    receipt: &OutlookSendReceipt,
+    throttle_retries: Option<u32>,
+    results: &mut [Result<OutlookSendReceipt, OutlookMailerError>],
+) -> (Vec<&'a BatchMessage>, Duration) {
+    let mut throttled = Vec::new();
+    let mut retry_after = Duration::ZERO;
+    let mut answered = vec![false; pending.len()];
+
+    for item in responses {
+        let Some(position) = pending
+            .iter()
+            .position(|message| message.index.to_string() == item.id)
+        else {
+            continue;
+        };
+        let message = pending[position];
+        answered[position] = true;
+
+        let status = StatusCode::from_u16(item.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        if status.is_success() {
+            results[message.index] = Ok(OutlookSendReceipt {
+                internet_message_id: message.internet_message_id.clone(),
+                ..receipt.clone()
+            });
+            continue;
+        }
+
+        let headers = item.header_map();
+        if let Some(throttle_retries) = throttle_retries.filter(|_| graph::is_throttled(status)) {
+            retry_after = retry_after.max(
+                graph::retry_after(&headers)
+                    .unwrap_or_else(|| graph::fallback_retry_after(throttle_retries)),
+            );
+            throttled.push(message);
+        }
+
+        let body = item
+            .body
+            .as_ref()
+            .and_then(|body| serde_json::to_vec(body).ok())
+            .unwrap_or_default();
+        results[message.index] = Err(OutlookMailerError::Graph(Box::new(
+            OutlookGraphError::from_response_parts(status, &headers, &body),
+        )));
+    }
+
+    // Never report a message as sent, or with the outcome of a previous attempt, without a sub-response.
+    for (message, _) in pending
+        .iter()
+        .zip(answered)
+        .filter(|(_, answered)| !answered)
+    {
+        results[message.index] = Err(OutlookMailerError::MissingBatchResponse);
+    }
+
+    (throttled, retry_after)
+}
+
+/// Messages sent in a single `$batch` request, with the total size of their JSON `sendMail` requests.
+struct Batch {
+    messages: Vec<BatchMessage>,
+    size: usize,
+}
+
+/// A parsed message of a batch, with its index in the input messages.
+struct BatchMessage {
+    index: usize,
+    internet_message_id: Option<String>,
+    mailbox_path: String,
+    graph_message: GraphMessage,
+}
+
+/// Microsoft Graph API JSON batch request.
+#[derive(Serialize)]
+struct BatchRequest<'a> {
+    requests: Vec<BatchRequestItem<'a>>,
+}
+
+impl<'a> BatchRequest<'a> {
+    /// A batch request of a `sendMail` sub-request per message, identified by the message's index.
+    fn send_mail(messages: &[&'a BatchMessage], save_to_sent_items: bool) -> Self {
+        Self {
+            requests: messages
+                .iter()
+                .map(|message| BatchRequestItem {
+                    id: message.index.to_string(),
+                    method: "POST",
+                    url: format!("/{}/sendMail", message.mailbox_path),
+                    headers: HashMap::from([("Content-Type", "application/json")]),
+                    body: message
+                        .graph_message
+                        .to_send_mail_request(save_to_sent_items),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Microsoft Graph API JSON batch sub-request.
+#[derive(Serialize)]
+struct BatchRequestItem<'a> {
+    id: String,
+    method: &'static str,
+    url: String,
+    headers: HashMap<&'static str, &'static str>,
+    body: SendMailRequest<'a>,
+}
+
+/// Microsoft Graph API JSON batch response.
+#[derive(Debug, Deserialize)]
+struct BatchResponse {
+    responses: Vec<BatchResponseItem>,
+}
+
+/// Microsoft Graph API JSON batch sub-response.
+#[derive(Debug, Deserialize)]
+struct BatchResponseItem {
+    id: String,
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<serde_json::Value>,
+}
+
+impl BatchResponseItem {
+    /// The sub-response headers, skipping headers which are not valid HTTP headers.
+    fn header_map(&self) -> HeaderMap {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                Some((
+                    HeaderName::try_from(name.as_str()).ok()?,
+                    HeaderValue::try_from(value.as_str()).ok()?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use async_mailer_core::mail_send::mail_builder::MessageBuilder;
+    use async_mailer_core::mail_send::smtp::message::IntoMessage;
+
+    use super::*;
+
+    fn batch_message(index: usize) -> BatchMessage {
+        mailbox_message(index, "users/from@example.com")
+    }
+
+    fn mailbox_message(index: usize, mailbox_path: &str) -> BatchMessage {
+        let message = MessageBuilder::new()
+            .from("from@example.com")
+            .to("to@example.com")
+            .subject("Subject")
+            .text_body("Body")
+            .into_message()
+            .unwrap();
+
+        BatchMessage {
+            index,
+            internet_message_id: Some(format!("message-{index}@example.com")),
+            mailbox_path: mailbox_path.to_string(),
+            graph_message: GraphMessage::parse(&message).unwrap(),
+        }
+    }
+
+    fn response(id: usize, status: u16, headers: &[(&str, &str)]) -> BatchResponseItem {
+        BatchResponseItem {
+            id: id.to_string(),
+            status,
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            body: None,
+        }
+    }
+
+    fn unsent_results(count: usize) -> Vec<Result<OutlookSendReceipt, OutlookMailerError>> {
+        (0..count)
+            .map(|_| Err(OutlookMailerError::MissingBatchResponse))
+            .collect()
+    }
+
+    fn batch_indexes(batches: &[Batch]) -> Vec<Vec<usize>> {
+        batches
+            .iter()
+            .map(|batch| batch.messages.iter().map(|message| message.index).collect())
+            .collect()
+    }
+
+    #[test]
+    fn groups_up_to_20_messages_per_batch() {
+        let mut batches = Vec::new();
+
+        for index in 0..45 {
+            let mailbox_path = format!("users/from-{}@example.com", index % 10);
+            add_to_batches(&mut batches, mailbox_message(index, &mailbox_path), 1);
+        }
+
+        let lengths: Vec<_> = batches.iter().map(|batch| batch.messages.len()).collect();
+        assert_eq!(lengths, [20, 20, 5]);
+        assert_eq!(batch_indexes(&batches)[2], [40, 41, 42, 43, 44]);
+    }
+
+    #[test]
+    fn groups_up_to_4_messages_per_mailbox_and_batch() {
+        let mut batches = Vec::new();
+
+        for index in 0..10 {
+            add_to_batches(
+                &mut batches,
+                mailbox_message(index, "users/a@example.com"),
+                1,
+            );
+        }
+        for index in 10..12 {
+            add_to_batches(
+                &mut batches,
+                mailbox_message(index, "users/b@example.com"),
+                1,
+            );
+        }
+
+        assert_eq!(
+            batch_indexes(&batches),
+            [vec![0, 1, 2, 3, 10, 11], vec![4, 5, 6, 7], vec![8, 9]]
+        );
+    }
+
+    #[test]
+    fn starts_new_batch_at_size_limit() {
+        let mut batches = Vec::new();
+
+        add_to_batches(&mut batches, batch_message(0), MAX_SEND_MAIL_SIZE / 2);
+        add_to_batches(&mut batches, batch_message(1), MAX_SEND_MAIL_SIZE / 2);
+        add_to_batches(&mut batches, batch_message(2), 1);
+        add_to_batches(&mut batches, batch_message(3), MAX_SEND_MAIL_SIZE);
+
+        assert_eq!(batch_indexes(&batches), [vec![0, 1], vec![2], vec![3]]);
+    }
+
+    #[test]
+    fn builds_send_mail_batch_request() {
+        let messages = [
+            mailbox_message(3, "users/a@example.com"),
+            mailbox_message(5, "users/b@example.com"),
+        ];
+        let pending: Vec<_> = messages.iter().collect();
+
+        let request = serde_json::to_value(BatchRequest::send_mail(&pending, false)).unwrap();
+
+        let requests = request["requests"].as_array().unwrap();
+        assert_eq!(requests.len(), 2);
+        for (request, (id, url)) in requests.iter().zip([
+            ("3", "/users/a@example.com/sendMail"),
+            ("5", "/users/b@example.com/sendMail"),
+        ]) {
+            assert_eq!(request["id"], id);
+            assert_eq!(request["method"], "POST");
+            assert_eq!(request["url"], url);
+            assert_eq!(request["headers"]["Content-Type"], "application/json");
+            assert_eq!(request["body"]["saveToSentItems"], false);
+            assert_eq!(request["body"]["message"]["subject"], "Subject");
+        }
+    }
+
+    #[test]
+    fn maps_sub_responses_to_messages_by_id() {
+        let messages = [batch_message(0), batch_message(1), batch_message(2)];
+        let pending: Vec<_> = messages.iter().collect();
+        let receipt = OutlookSendReceipt {
+            request_id: Some("request".to_string()),
+            ..Default::default()
+        };
+        let mut results = unsent_results(3);
+
+        // Sub-responses may arrive in any order.
+        let (throttled, _) = record_responses(
+            &pending,
+            vec![
+                response(2, 202, &[]),
+                response(0, 400, &[]),
+                response(1, 202, &[]),
+            ],
+            &receipt,
+            Some(0),
+            &mut results,
+        );
+
+        assert!(throttled.is_empty());
+        assert!(matches!(
+            &results[0],
+            Err(OutlookMailerError::Graph(error)) if error.status == StatusCode::BAD_REQUEST
+        ));
+        for index in [1, 2] {
+            let receipt = results[index].as_ref().unwrap();
+            assert_eq!(receipt.request_id.as_deref(), Some("request"));
+            assert_eq!(
+                receipt.internet_message_id,
+                Some(format!("message-{index}@example.com"))
+            );
+        }
+    }
+
+    #[test]
+    fn reports_messages_without_sub_response_as_failed() {
+        let messages = [batch_message(0), batch_message(1)];
+        let pending: Vec<_> = messages.iter().collect();
+        let mut results = unsent_results(2);
+
+        record_responses(
+            &pending,
+            vec![response(0, 202, &[]), response(7, 202, &[])],
+            &OutlookSendReceipt::default(),
+            Some(0),
+            &mut results,
+        );
+
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(OutlookMailerError::MissingBatchResponse)
+        ));
+    }
+
+    #[test]
+    fn replaces_previous_outcome_of_retried_message_without_sub_response() {
+        let messages = [batch_message(0)];
+        let pending: Vec<_> = messages.iter().collect();
+        let mut results = unsent_results(1);
+
+        let (throttled, _) = record_responses(
+            &pending,
+            vec![response(0, 429, &[])],
+            &OutlookSendReceipt::default(),
+            Some(0),
+            &mut results,
+        );
+        assert_eq!(throttled.len(), 1);
+
+        record_responses(
+            &throttled,
+            Vec::new(),
+            &OutlookSendReceipt::default(),
+            Some(1),
+            &mut results,
+        );
+
+        assert!(matches!(
+            results[0],
+            Err(OutlookMailerError::MissingBatchResponse)
+        ));
+    }
+
+    #[test]
+    fn returns_throttled_messages_with_longest_retry_after() {
+        let messages = [batch_message(0), batch_message(1), batch_message(2)];
+        let pending: Vec<_> = messages.iter().collect();
+        let mut results = unsent_results(3);
+
+        let (throttled, retry_after) = record_responses(
+            &pending,
+            vec![
+                response(0, 429, &[("Retry-After", "3")]),
+                response(1, 503, &[("Retry-After", "7")]),
+                response(2, 202, &[]),
+            ],
+            &OutlookSendReceipt::default(),
+            Some(0),
+            &mut results,
+        );
+
+        let throttled: Vec<_> = throttled.iter().map(|message| message.index).collect();
+        assert_eq!(throttled, [0, 1]);
+        assert_eq!(retry_after, Duration::from_secs(7));
+        assert!(matches!(
+            &results[0],
+            Err(OutlookMailerError::Graph(error)) if error.status == StatusCode::TOO_MANY_REQUESTS
+        ));
+    }
+
+    #[test]
+    fn does_not_retry_throttled_messages_without_retry_budget() {
+        let messages = [batch_message(0)];
+        let pending: Vec<_> = messages.iter().collect();
+        let mut results = unsent_results(1);
+
+        let (throttled, _) = record_responses(
+            &pending,
+            vec![response(0, 429, &[])],
+            &OutlookSendReceipt::default(),
+            None,
+            &mut results,
+        );
+
+        assert!(throttled.is_empty());
+        assert!(matches!(
+            &results[0],
+            Err(OutlookMailerError::Graph(error)) if error.status == StatusCode::TOO_MANY_REQUESTS
+        ));
+    }
+}
@@ -0,0 +1,404 @@
+//! Access token caching for the [`OutlookMailer`](crate::OutlookMailer), e.g. across process restarts.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use crate::OutlookAccessToken;
+
+/// Type-erased error returned by a [`TokenCache`].
+pub type TokenCacheError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Cache of Microsoft Graph API access tokens, consulted by the [`OutlookMailer`](crate::OutlookMailer)
+/// before requesting a new access token from its [`TokenProvider`](crate::TokenProvider).
+///
+/// Tokens are stored under a key identifying the token endpoint, OAuth2 app and scope,
+/// and the identity returned by [`TokenProvider::cache_key`](crate::TokenProvider::cache_key),
+/// so a single cache may be shared by multiple mailers.
+/// Mailers sending on behalf of different identities with the same OAuth2 app, e.g. different delegated users,
+/// must only share a cache if their token providers return distinct cache keys,
+/// e.g. set with [`DelegatedTokenProvider::account`](crate::DelegatedTokenProvider::account).
+///
+/// Implemented by:
+///
+/// - [`InMemoryTokenCache`], the default, holding tokens for the lifetime of the process.
+/// - [`FileTokenCache`], persisting tokens across process restarts.
+///
+/// Cache errors are not fatal: the mailer falls back to requesting a new access token.
+///
+/// Implementations persisting tokens should also persist [`OutlookAccessToken::issued_at`],
+/// and restore it with [`OutlookAccessToken::with_issued_at`], as the refresh margin depends on the token's lifetime.
+#[async_trait]
+pub trait TokenCache: std::fmt::Debug + Send + Sync {
+    /// Load the access token stored under `key`, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`TokenCacheError`] if the cache cannot be read.
+    async fn load(&self, key: &str) -> Result<Option<OutlookAccessToken>, TokenCacheError>;
+
+    /// Store the access token under `key`, replacing any previous token.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`TokenCacheError`] if the cache cannot be written.
+    async fn store(&self, key: &str, token: &OutlookAccessToken) -> Result<(), TokenCacheError>;
+
+    /// Remove the access token stored under `key`, e.g. after it was rejected by the Microsoft Graph API.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`TokenCacheError`] if the cache cannot be written.
+    async fn remove(&self, key: &str) -> Result<(), TokenCacheError>;
+}
+
+/// In-memory [`TokenCache`], holding access tokens for the lifetime of the process.
+///
+/// This is the default token cache of the [`OutlookMailer`](crate::OutlookMailer).
+#[derive(Debug, Default)]
+pub struct InMemoryTokenCache {
+    tokens: Mutex<HashMap<String, OutlookAccessToken>>,
+}
+
+#[async_trait]
+impl TokenCache for InMemoryTokenCache {
+    async fn load(&self, key: &str) -> Result<Option<OutlookAccessToken>, TokenCacheError> {
+        Ok(self.tokens.lock().await.get(key).cloned())
+    }
+
+    async fn store(&self, key: &str, token: &OutlookAccessToken) -> Result<(), TokenCacheError> {
+        self.tokens
+            .lock()
+            .await
+            .insert(key.to_string(), token.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), TokenCacheError> {
+        self.tokens.lock().await.remove(key);
+        Ok(())
+    }
+}
+
+/// File-based [`TokenCache`], persisting access tokens across process restarts,
+/// e.g. for frequently cold-started serverless workers.
+///
+/// Tokens are stored as JSON. The file is replaced atomically on each write,
+/// and on Unix created with permissions `0600`, readable and writable by the owner only.
+/// Multiple processes may share the file. A corrupt file is overwritten on the next write.
+#[derive(Debug)]
+pub struct FileTokenCache {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl FileTokenCache {
+    /// Create a new file-based token cache, storing tokens in the file at `path`.
+    ///
+    /// The file is created on the first write. Its parent directory must exist.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Read all cached tokens, treating a missing file as an empty cache.
+    async fn read(&self) -> Result<HashMap<String, CachedToken>, TokenCacheError> {
+        match tokio::fs::read(&self.path).await {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(HashMap::new()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// Read all cached tokens to be updated, treating a corrupt file as an empty cache, to be overwritten.
+    async fn read_for_update(&self) -> Result<HashMap<String, CachedToken>, TokenCacheError> {
+        match self.read().await {
+            Err(error) if error.is::<serde_json::Error>() => {
+                #[cfg(feature = "tracing")]
+                warn!(?error, path = ?self.path, "Discarding corrupt token cache file");
+
+                Ok(HashMap::new())
+            }
+            result => result,
+        }
+    }
+
+    /// Replace the cache file atomically, writing to a temporary file with restrictive permissions first.
+    ///
+    /// The temporary file name is unique per write, so processes sharing the cache file
+    /// do not write to each other's temporary files.
+    async fn write(&self, tokens: &HashMap<String, CachedToken>) -> Result<(), TokenCacheError> {
+        let contents = serde_json::to_vec(tokens)?;
+
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(format!(
+            ".{pid}.{suffix}.tmp",
+            pid = std::process::id(),
+            suffix = uuid::Uuid::new_v4().simple()
+        ));
+
+        let result = async {
+            let mut options = tokio::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+
+            let mut file = options.open(&temporary_path).await?;
+            file.write_all(&contents).await?;
+            file.sync_all().await?;
+            drop(file);
+
+            tokio::fs::rename(&temporary_path, &self.path).await
+        }
+        .await;
+
+        if result.is_err() {
+            // Clean up the temporary file, if it was created. The original error is returned regardless.
+            let _ = tokio::fs::remove_file(&temporary_path).await;
+        }
+
+        Ok(result?)
+    }
+}
+
+#[async_trait]
+impl TokenCache for FileTokenCache {
+    async fn load(&self, key: &str) -> Result<Option<OutlookAccessToken>, TokenCacheError> {
+        let _lock = self.lock.lock().await;
+
+        Ok(self
+            .read()
+            .await?
+            .get(key)
+            .map(CachedToken::to_access_token))
+    }
+
+    async fn store(&self, key: &str, token: &OutlookAccessToken) -> Result<(), TokenCacheError> {
+        let _lock = self.lock.lock().await;
+
+        let now = unix_time_now();
+        let mut tokens = self.read_for_update().await?;
+        // Drop expired tokens, so the file does not grow indefinitely.
+        tokens.retain(|_, token| token.expires_at > now);
+        tokens.insert(key.to_string(), CachedToken::from_access_token(token));
+
+        self.write(&tokens).await
+    }
+
+    async fn remove(&self, key: &str) -> Result<(), TokenCacheError> {
+        let _lock = self.lock.lock().await;
+
+        let mut tokens = self.read_for_update().await?;
+        if tokens.remove(key).is_some() {
+            self.write(&tokens).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Access token persisted by the [`FileTokenCache`].
+///
+/// [`Instant`]s are process-local, so the issue and expiry times are persisted as UNIX timestamps.
+/// The issue time is restored, so the refresh margin is derived from the token's original lifetime.
+#[derive(Debug, Deserialize, Serialize)]
+struct CachedToken {
+    access_token: String,
+    #[serde(default)]
+    issued_at: Option<u64>,
+    expires_at: u64,
+}
+
+impl CachedToken {
+    fn from_access_token(token: &OutlookAccessToken) -> Self {
+        let now = Instant::now();
+        let unix_now = unix_time_now();
+        let issued_ago = now.saturating_duration_since(token.issued_at());
+        let expires_in = token.expires_at().saturating_duration_since(now);
+
+        Self {
+            access_token: token.secret().expose_secret().to_string(),
+            issued_at: Some(unix_now.saturating_sub(issued_ago.as_secs())),
+            expires_at: unix_now + expires_in.as_secs(),
+        }
+    }
+
+    fn to_access_token(&self) -> OutlookAccessToken {
+        let now = Instant::now();
+        let unix_now = unix_time_now();
+        let expires_in = Duration::from_secs(self.expires_at.saturating_sub(unix_now));
+        // Tokens cached without an issue time are treated as issued now.
+        let issued_ago = Duration::from_secs(
+            self.issued_at
+                .map_or(0, |issued_at| unix_now.saturating_sub(issued_at)),
+        );
+
+        OutlookAccessToken::with_issued_at(
+            SecretString::from(self.access_token.as_str()),
+            now.checked_sub(issued_ago).unwrap_or(now),
+            now + expires_in,
+        )
+    }
+}
+
+/// Seconds since the UNIX epoch.
+fn unix_time_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A cache file path in the system's temporary directory, unique per test run.
+    fn temporary_cache_path() -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "async-mailer-outlook-token-cache-{}.json",
+            uuid::Uuid::new_v4().simple()
+        ))
+    }
+
+    fn access_token(secret: &str, expires_in: Duration) -> OutlookAccessToken {
+        OutlookAccessToken::new(SecretString::from(secret), Instant::now() + expires_in)
+    }
+
+    #[tokio::test]
+    async fn file_token_cache_round_trip() {
+        let path = temporary_cache_path();
+        let cache = FileTokenCache::new(&path);
+
+        assert!(cache.load("key").await.unwrap().is_none());
+
+        cache
+            .store("key", &access_token("secret", Duration::from_secs(3600)))
+            .await
+            .unwrap();
+        cache
+            .store("other", &access_token("other", Duration::from_secs(3600)))
+            .await
+            .unwrap();
+
+        // A new cache instance reads the tokens persisted by the first one.
+        let loaded = FileTokenCache::new(&path)
+            .load("key")
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(loaded.secret().expose_secret(), "secret");
+        let expires_in = loaded
+            .expires_at()
+            .saturating_duration_since(Instant::now());
+        assert!(expires_in > Duration::from_secs(3590) && expires_in <= Duration::from_secs(3600));
+
+        cache.remove("key").await.unwrap();
+        assert!(cache.load("key").await.unwrap().is_none());
+        assert!(cache.load("other").await.unwrap().is_some());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_token_cache_restores_token_lifetime() {
+        let path = temporary_cache_path();
+        let cache = FileTokenCache::new(&path);
+
+        // A token with a 20 second lifetime, expiring in 4 seconds, within half of its lifetime.
+        let now = Instant::now();
+        let token = OutlookAccessToken::with_issued_at(
+            SecretString::from("secret"),
+            now - Duration::from_secs(16),
+            now + Duration::from_secs(4),
+        );
+        assert!(token.needs_refresh());
+
+        cache.store("key", &token).await.unwrap();
+
+        let loaded = FileTokenCache::new(&path)
+            .load("key")
+            .await
+            .unwrap()
+            .unwrap();
+        let issued_ago = Instant::now().saturating_duration_since(loaded.issued_at());
+        assert!(issued_ago >= Duration::from_secs(15) && issued_ago <= Duration::from_secs(17));
+        assert!(loaded.needs_refresh());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_token_cache_drops_expired_tokens() {
+        let path = temporary_cache_path();
+        let cache = FileTokenCache::new(&path);
+
+        cache
+            .store("expired", &access_token("expired", Duration::ZERO))
+            .await
+            .unwrap();
+        cache
+            .store("key", &access_token("secret", Duration::from_secs(3600)))
+            .await
+            .unwrap();
+
+        assert!(cache.load("expired").await.unwrap().is_none());
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_token_cache_overwrites_corrupt_file() {
+        let path = temporary_cache_path();
+        tokio::fs::write(&path, b"not json").await.unwrap();
+        let cache = FileTokenCache::new(&path);
+
+        assert!(cache.load("key").await.is_err());
+        cache.remove("key").await.unwrap();
+
+        cache
+            .store("key", &access_token("secret", Duration::from_secs(3600)))
+            .await
+            .unwrap();
+
+        let loaded = cache.load("key").await.unwrap().unwrap();
+        assert_eq!(loaded.secret().expose_secret(), "secret");
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn file_token_cache_leaves_no_temporary_files() {
+        let directory = std::env::temp_dir().join(format!(
+            "async-mailer-outlook-token-cache-{}",
+            uuid::Uuid::new_v4().simple()
+        ));
+        tokio::fs::create_dir(&directory).await.unwrap();
+        let cache = FileTokenCache::new(directory.join("tokens.json"));
+
+        cache
+            .store("key", &access_token("secret", Duration::from_secs(3600)))
+            .await
+            .unwrap();
+
+        let mut entries = tokio::fs::read_dir(&directory).await.unwrap();
+        let mut file_names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            file_names.push(entry.file_name());
+        }
+        assert_eq!(file_names, ["tokens.json"]);
+
+        tokio::fs::remove_dir_all(&directory).await.unwrap();
+    }
+}
@@ -0,0 +1,397 @@
+//! Delegated-user authentication of the [`OutlookMailer`](crate::OutlookMailer),
+//! using the OAuth2 device code and refresh token grants.
+//!
+//! See <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-device-code>
+//! and <https://learn.microsoft.com/en-us/entra/identity-platform/v2-oauth2-auth-code-flow#refresh-the-access-token>
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use serde::Deserialize;
+use tokio::sync::Mutex;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, info, instrument};
+
+use crate::token::{post_token_form, TokenEndpointResponse, TokenRequest};
+use crate::{OutlookAccessToken, OutlookAccessTokenError, TokenProvider};
+
+/// Scope requesting a refresh token alongside the access token.
+const OFFLINE_ACCESS: &str = "offline_access";
+
+/// OAuth2 device code grant type.
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Additional polling delay requested by the `slow_down` device code error.
+const DEVICE_CODE_SLOW_DOWN: Duration = Duration::from_secs(5);
+
+/// Type-erased error returned by a [`RefreshTokenStore`].
+pub type RefreshTokenStoreError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Persistent storage of the refresh token used by a [`DelegatedTokenProvider`].
+///
+/// The Microsoft Identity service rotates refresh tokens:
+/// each refresh returns a new refresh token, which is passed to [`RefreshTokenStore::store`]
+/// and must be used for the next refresh.
+/// Implement this trait to persist the refresh token across process restarts,
+/// e.g. in a database, a secrets manager or the operating system keychain.
+#[async_trait]
+pub trait RefreshTokenStore: std::fmt::Debug + Send + Sync {
+    /// Load the current refresh token, if any.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`RefreshTokenStoreError`] if the refresh token cannot be loaded.
+    async fn load(&self) -> Result<Option<SecretString>, RefreshTokenStoreError>;
+
+    /// Store a new refresh token, replacing the previous one.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`RefreshTokenStoreError`] if the refresh token cannot be stored.
+    async fn store(&self, refresh_token: &SecretString) -> Result<(), RefreshTokenStoreError>;
+}
+
+/// In-memory [`RefreshTokenStore`], losing the refresh token when the process exits.
+#[derive(Debug, Default)]
+pub struct InMemoryRefreshTokenStore {
+    refresh_token: Mutex<Option<SecretString>>,
+}
+
+impl InMemoryRefreshTokenStore {
+    /// Create a new in-memory refresh token store, optionally holding an initial refresh token.
+    pub fn new(refresh_token: Option<SecretString>) -> Self {
+        Self {
+            refresh_token: Mutex::new(refresh_token),
+        }
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for InMemoryRefreshTokenStore {
+    async fn load(&self) -> Result<Option<SecretString>, RefreshTokenStoreError> {
+        Ok(self.refresh_token.lock().await.clone())
+    }
+
+    async fn store(&self, refresh_token: &SecretString) -> Result<(), RefreshTokenStoreError> {
+        *self.refresh_token.lock().await = Some(refresh_token.clone());
+        Ok(())
+    }
+}
+
+/// Device code sign-in instructions, passed to the prompt callback of a [`DelegatedTokenProvider`].
+///
+/// The user must visit the verification URI and enter the user code to sign in.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct DeviceCodePrompt {
+    /// The code the user enters at the verification URI.
+    pub user_code: String,
+
+    /// The URI the user visits to sign in, e.g. `https://microsoft.com/devicelogin`.
+    pub verification_uri: String,
+
+    /// Human-readable sign-in instructions, as provided by the Microsoft Identity service.
+    pub message: String,
+
+    /// Time until the device code expires.
+    pub expires_in: Duration,
+}
+
+/// Callback displaying [`DeviceCodePrompt`] sign-in instructions to the user.
+pub type DeviceCodePromptCallback = Arc<dyn Fn(&DeviceCodePrompt) + Send + Sync>;
+
+/// Delegated-user [`TokenProvider`], sending mail as the signed-in user.
+///
+/// Access tokens are retrieved using the OAuth2 refresh token grant,
+/// with the refresh token loaded from, and rotated refresh tokens saved to, a [`RefreshTokenStore`].
+///
+/// If no refresh token is stored, and a device code prompt was configured with
+/// [`DelegatedTokenProvider::device_code_prompt`], the user is signed in interactively
+/// using the OAuth2 device code flow, e.g. in CLI tools.
+///
+/// Send from the signed-in user's mailbox by configuring the mailer with
+/// [`OutlookMailbox::Me`](crate::OutlookMailbox::Me).
+/// The `offline_access` scope is requested automatically.
+#[derive(Clone)]
+pub struct DelegatedTokenProvider {
+    store: Arc<dyn RefreshTokenStore>,
+    client_secret: Option<SecretString>,
+    device_code_prompt: Option<DeviceCodePromptCallback>,
+    account: Option<String>,
+}
+
+impl DelegatedTokenProvider {
+    /// Create a new delegated-user token provider, using the refresh token held by `store`.
+    pub fn new(store: impl RefreshTokenStore + 'static) -> Self {
+        Self {
+            store: Arc::new(store),
+            client_secret: None,
+            device_code_prompt: None,
+            account: None,
+        }
+    }
+
+    /// Identify the signed-in user account, e.g. by its user principal name,
+    /// distinguishing its access tokens from those of other users in a shared [`TokenCache`](crate::TokenCache).
+    ///
+    /// Without an account, a token cache must not be shared with mailers signed in as other users,
+    /// or they would send mail with each other's access tokens.
+    pub fn account(mut self, account: impl Into<String>) -> Self {
+        self.account = Some(account.into());
+        self
+    }
+
+    /// Authenticate refresh token requests with a client secret,
+    /// as required for confidential client apps.
+    ///
+    /// Public client apps, such as CLI tools, do not use a client secret.
+    pub fn client_secret(mut self, client_secret: SecretString) -> Self {
+        self.client_secret = Some(client_secret);
+        self
+    }
+
+    /// Sign the user in with the OAuth2 device code flow if no refresh token is stored,
+    /// displaying the sign-in instructions through `prompt`.
+    ///
+    /// The OAuth2 app must allow public client flows.
+    /// Messages sent while the user signs in wait for the sign-in to complete, or for the device code to expire.
+    pub fn device_code_prompt(
+        mut self,
+        prompt: impl Fn(&DeviceCodePrompt) + Send + Sync + 'static,
+    ) -> Self {
+        self.device_code_prompt = Some(Arc::new(prompt));
+        self
+    }
+
+    /// Exchange the refresh token for a new access token and a rotated refresh token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the refresh token.
+    async fn refresh_token_grant(
+        &self,
+        request: &TokenRequest<'_>,
+        refresh_token: &SecretString,
+    ) -> Result<DelegatedTokenResponse, OutlookAccessTokenError> {
+        let scope = with_offline_access(request.scope());
+
+        let mut form_data = vec![
+            ("client_id", request.client_id()),
+            ("grant_type", "refresh_token"),
+            ("scope", &scope),
+            ("refresh_token", refresh_token.expose_secret()),
+        ];
+        if let Some(client_secret) = &self.client_secret {
+            form_data.push(("client_secret", client_secret.expose_secret()));
+        }
+
+        match post_token_form(request, request.token_url(), &form_data).await? {
+            TokenEndpointResponse::Success(response) => Ok(response),
+            TokenEndpointResponse::Error(error) => Err(error.into()),
+        }
+    }
+
+    /// Sign the user in with the OAuth2 device code flow, polling the token endpoint until the user has signed in,
+    /// or the device code expires.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending a request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if a response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if a response body cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookAccessTokenError::OAuth2`] error if the user declines the sign-in,
+    /// or the device code expires.
+    async fn device_code_grant(
+        &self,
+        request: &TokenRequest<'_>,
+        prompt: &DeviceCodePromptCallback,
+    ) -> Result<DelegatedTokenResponse, OutlookAccessTokenError> {
+        let scope = with_offline_access(request.scope());
+        let device_code_url = format!(
+            "{}/devicecode",
+            request
+                .token_url()
+                .strip_suffix("/token")
+                .unwrap_or(request.token_url())
+        );
+
+        let device_code: DeviceCodeResponse = match post_token_form(
+            request,
+            &device_code_url,
+            &[("client_id", request.client_id()), ("scope", &scope)],
+        )
+        .await?
+        {
+            TokenEndpointResponse::Success(response) => response,
+            TokenEndpointResponse::Error(error) => return Err(error.into()),
+        };
+
+        // Enforce the device code's lifetime locally, rather than relying on an `expired_token` error.
+        let deadline = Instant::now() + Duration::from_secs(device_code.expires_in);
+
+        prompt(&DeviceCodePrompt {
+            user_code: device_code.user_code,
+            verification_uri: device_code.verification_uri,
+            message: device_code.message,
+            expires_in: Duration::from_secs(device_code.expires_in),
+        });
+
+        let mut interval = Duration::from_secs(device_code.interval);
+
+        loop {
+            if Instant::now() + interval >= deadline {
+                return Err(OutlookAccessTokenError::OAuth2(
+                    "expired_token: the device code expired before the user signed in".to_string(),
+                ));
+            }
+
+            tokio::time::sleep(interval).await;
+
+            let form_data = [
+                ("client_id", request.client_id()),
+                ("grant_type", DEVICE_CODE_GRANT_TYPE),
+                ("device_code", device_code.device_code.as_str()),
+            ];
+
+            match post_token_form(request, request.token_url(), &form_data).await? {
+                TokenEndpointResponse::Success(response) => return Ok(response),
+                TokenEndpointResponse::Error(error) => match error.error.as_str() {
+                    // The user has not yet completed the sign-in.
+                    "authorization_pending" => {}
+                    "slow_down" => interval += DEVICE_CODE_SLOW_DOWN,
+                    _ => return Err(error.into()),
+                },
+            }
+        }
+    }
+}
+
+impl std::fmt::Debug for DelegatedTokenProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DelegatedTokenProvider")
+            .field("store", &self.store)
+            .field("client_secret", &self.client_secret)
+            .field("device_code_prompt", &self.device_code_prompt.is_some())
+            .field("account", &self.account)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl TokenProvider for DelegatedTokenProvider {
+    /// Retrieve a delegated-user access token, using the stored refresh token,
+    /// or signing the user in with the device code flow if no refresh token is stored.
+    ///
+    /// The rotated refresh token is saved to the [`RefreshTokenStore`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::RefreshTokenStore`] error if the refresh token cannot be loaded or stored.
+    ///
+    /// Returns an [`OutlookAccessTokenError::MissingRefreshToken`] error if no refresh token is stored,
+    /// and no device code prompt is configured.
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending a request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if a response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if a response body cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request.
+    #[cfg_attr(feature = "tracing", instrument(skip(request)))]
+    async fn access_token(
+        &self,
+        request: &TokenRequest<'_>,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError> {
+        let requested_at = Instant::now();
+
+        let refresh_token = self
+            .store
+            .load()
+            .await
+            .map_err(OutlookAccessTokenError::RefreshTokenStore)?;
+
+        let response = match (refresh_token, &self.device_code_prompt) {
+            (Some(refresh_token), _) => {
+                #[cfg(feature = "tracing")]
+                debug!("Refreshing delegated-user access token...");
+
+                self.refresh_token_grant(request, &refresh_token).await?
+            }
+            (None, Some(prompt)) => {
+                #[cfg(feature = "tracing")]
+                info!("No refresh token stored; signing in with device code flow...");
+
+                self.device_code_grant(request, prompt).await?
+            }
+            (None, None) => return Err(OutlookAccessTokenError::MissingRefreshToken),
+        };
+
+        if let Some(refresh_token) = response.refresh_token {
+            self.store
+                .store(&SecretString::from(refresh_token))
+                .await
+                .map_err(OutlookAccessTokenError::RefreshTokenStore)?;
+        }
+
+        Ok(OutlookAccessToken::with_issued_at(
+            SecretString::from(response.access_token),
+            requested_at,
+            // Measure validity from the time of the request, erring on the side of an early refresh.
+            requested_at + Duration::from_secs(response.expires_in),
+        ))
+    }
+
+    fn cache_key(&self) -> Option<&str> {
+        self.account.as_deref()
+    }
+}
+
+/// Add the `offline_access` scope to the space-separated `scope`, unless already present.
+fn with_offline_access(scope: &str) -> String {
+    if scope.split(' ').any(|scope| scope == OFFLINE_ACCESS) {
+        scope.to_string()
+    } else {
+        format!("{scope} {OFFLINE_ACCESS}")
+    }
+}
+
+/// Microsoft Identity service device code JSON response.
+#[derive(Debug, Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    message: String,
+    expires_in: u64,
+    interval: u64,
+}
+
+/// Microsoft Identity service delegated-user access token JSON response.
+#[derive(Debug, Deserialize)]
+struct DelegatedTokenResponse {
+    expires_in: u64,
+    access_token: String,
+    refresh_token: Option<String>,
+}
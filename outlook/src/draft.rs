@@ -0,0 +1,312 @@
+//! Sending of messages through the Microsoft Graph API JSON endpoints and draft messages.
+//!
+//! The `sendMail` endpoints limit the request size to 4 MB.
+//! Larger messages are instead created as a draft message without attachments.
+//! Attachments are then added individually, uploading large attachments in chunks
+//! through an upload session, before the draft is sent.
+//!
+//! See <https://learn.microsoft.com/en-us/graph/outlook-large-attachments>
+
+use reqwest::header::{CONTENT_RANGE, CONTENT_TYPE};
+use serde::Deserialize;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, instrument, warn};
+
+use crate::message::{Attachment, GraphMessage};
+use crate::{graph, OutlookMailer, OutlookMailerError, OutlookSendReceipt};
+
+/// Largest request body accepted by the `sendMail` endpoints.
+pub(crate) const MAX_SEND_MAIL_SIZE: usize = 4 * 1024 * 1024;
+
+/// Attachments of this size or larger must be uploaded through an upload session.
+const MIN_UPLOAD_SESSION_ATTACHMENT_SIZE: usize = 3 * 1024 * 1024;
+
+/// Upload session chunk size. Must be a multiple of 320 KiB.
+const UPLOAD_CHUNK_SIZE: usize = 10 * 320 * 1024;
+
+impl OutlookMailer {
+    /// Send a parsed message from the mailbox at `mailbox_path` through the JSON `sendMail` endpoint,
+    /// or, if the request would exceed the size limit, through a draft message.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if no valid access token can be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending a request fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if a response body cannot be received.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a response body cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if the message exceeds the request size limit
+    /// without its attachments.
+    pub(crate) async fn send_graph_message(
+        &self,
+        mailbox_path: &str,
+        mut message: GraphMessage,
+        save_to_sent_items: bool,
+        client_request_id: &str,
+    ) -> Result<OutlookSendReceipt, OutlookMailerError> {
+        let request_body = message.to_send_mail_json(save_to_sent_items);
+
+        if request_body.len() <= MAX_SEND_MAIL_SIZE {
+            let response = self
+                .send_graph_request(Some(client_request_id), |http_client| {
+                    http_client
+                        .post(format!(
+                            "{graph_url}/{mailbox_path}/sendMail",
+                            graph_url = self.graph_url
+                        ))
+                        .header(CONTENT_TYPE, "application/json")
+                        .body(request_body.clone())
+                })
+                .await?;
+
+            return Ok(OutlookSendReceipt::from_response(&response));
+        }
+
+        // The `sendMail` endpoint rejects large messages.
+        // Send through a draft message instead, uploading attachments separately.
+        #[cfg(feature = "tracing")]
+        debug!(
+            size = request_body.len(),
+            "Message exceeds the sendMail size limit; sending as draft message..."
+        );
+
+        if !save_to_sent_items {
+            message.delete_after_submit();
+        }
+
+        self.send_draft(mailbox_path, &message, client_request_id)
+            .await
+    }
+
+    /// Create a draft message from a parsed message in the mailbox at `mailbox_path`,
+    /// and add its attachments.
+    ///
+    /// Returns the Microsoft Graph API message id of the draft.
+    ///
+    /// If adding attachments fails, the draft is deleted on a best-effort basis.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if no valid access token can be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending a request fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if a response body cannot be received.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a response body cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if the message exceeds the request size limit
+    /// without its attachments.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, message)))]
+    pub(crate) async fn create_draft_message(
+        &self,
+        mailbox_path: &str,
+        message: &GraphMessage,
+        client_request_id: Option<&str>,
+    ) -> Result<String, OutlookMailerError> {
+        // Attachments are added separately, but the message itself, e.g. a large body,
+        // must fit into a single request.
+        let request_body = message.to_message_json();
+        if request_body.len() > MAX_SEND_MAIL_SIZE {
+            return Err(OutlookMailerError::MessageTooLarge(request_body.len()));
+        }
+
+        let response = self
+            .send_graph_request(client_request_id, |http_client| {
+                http_client
+                    .post(self.messages_url(mailbox_path))
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(request_body.clone())
+            })
+            .await?;
+        let draft: Draft =
+            serde_json::from_slice(&response.body).map_err(OutlookMailerError::ParseResponse)?;
+
+        #[cfg(feature = "tracing")]
+        debug!(draft_id = draft.id, "Created draft message");
+
+        let message_url = self.message_url(mailbox_path, &draft.id);
+
+        for attachment in &message.attachments {
+            if let Err(error) = self
+                .add_attachment(&message_url, attachment, client_request_id)
+                .await
+            {
+                self.delete_draft(&message_url).await;
+                return Err(error);
+            }
+        }
+
+        Ok(draft.id)
+    }
+
+    /// Send a parsed message by creating a draft message in the mailbox at `mailbox_path`,
+    /// adding its attachments and sending the draft.
+    ///
+    /// If adding attachments or sending fails, the draft is deleted on a best-effort basis.
+    ///
+    /// Returns a receipt holding the Microsoft Graph API message id of the draft.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if no valid access token can be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending a request fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if a response body cannot be received.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a response body cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if the message exceeds the request size limit
+    /// without its attachments.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, message)))]
+    pub(crate) async fn send_draft(
+        &self,
+        mailbox_path: &str,
+        message: &GraphMessage,
+        client_request_id: &str,
+    ) -> Result<OutlookSendReceipt, OutlookMailerError> {
+        let draft_id = self
+            .create_draft_message(mailbox_path, message, Some(client_request_id))
+            .await?;
+        let message_url = self.message_url(mailbox_path, &draft_id);
+
+        let result = self
+            .send_graph_request(Some(client_request_id), |http_client| {
+                http_client.post(format!("{message_url}/send"))
+            })
+            .await;
+
+        if result.is_err() {
+            self.delete_draft(&message_url).await;
+        }
+
+        Ok(OutlookSendReceipt {
+            message_id: Some(draft_id),
+            ..OutlookSendReceipt::from_response(&result?)
+        })
+    }
+
+    /// Delete the draft message at `message_url` on a best-effort basis,
+    /// so no half-built draft is left behind in the mailbox.
+    async fn delete_draft(&self, message_url: &str) {
+        let _deleted = self
+            .send_graph_request(None, |http_client| http_client.delete(message_url))
+            .await;
+
+        #[cfg(feature = "tracing")]
+        if let Err(error) = _deleted {
+            warn!(?error, "Failed to delete draft message");
+        }
+    }
+
+    /// The Microsoft Graph API messages collection URL of the mailbox at `mailbox_path`.
+    fn messages_url(&self, mailbox_path: &str) -> String {
+        format!(
+            "{graph_url}/{mailbox_path}/messages",
+            graph_url = self.graph_url
+        )
+    }
+
+    /// The Microsoft Graph API URL of the message `message_id` in the mailbox at `mailbox_path`.
+    fn message_url(&self, mailbox_path: &str, message_id: &str) -> String {
+        format!(
+            "{messages_url}/{message_id}",
+            messages_url = self.messages_url(mailbox_path)
+        )
+    }
+
+    /// Add an attachment to the draft message at `message_url`.
+    ///
+    /// Small attachments are added in a single request;
+    /// large attachments are uploaded in chunks through an upload session.
+    async fn add_attachment(
+        &self,
+        message_url: &str,
+        attachment: &Attachment,
+        client_request_id: Option<&str>,
+    ) -> Result<(), OutlookMailerError> {
+        if attachment.contents.len() < MIN_UPLOAD_SESSION_ATTACHMENT_SIZE {
+            let file_attachment = attachment.to_file_attachment();
+
+            self.send_graph_request(client_request_id, |http_client| {
+                http_client
+                    .post(format!("{message_url}/attachments"))
+                    .json(&file_attachment)
+            })
+            .await?;
+
+            return Ok(());
+        }
+
+        let response = self
+            .send_graph_request(client_request_id, |http_client| {
+                http_client
+                    .post(format!("{message_url}/attachments/createUploadSession"))
+                    .json(&serde_json::json!({
+                        "AttachmentItem": attachment.to_attachment_item(),
+                    }))
+            })
+            .await?;
+        let upload_session: UploadSession =
+            serde_json::from_slice(&response.body).map_err(OutlookMailerError::ParseResponse)?;
+
+        let total_size = attachment.contents.len();
+        let mut offset = 0;
+
+        for chunk in attachment.contents.chunks(UPLOAD_CHUNK_SIZE) {
+            let end = offset + chunk.len();
+
+            #[cfg(feature = "tracing")]
+            debug!(
+                name = attachment.name,
+                offset, end, total_size, "Uploading attachment chunk..."
+            );
+
+            // The upload URL is pre-authenticated, and must not be sent an `Authorization` header.
+            let response = self
+                .http_client
+                .put(&upload_session.upload_url)
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {offset}-{last}/{total_size}", last = end - 1),
+                )
+                .body(chunk.to_vec())
+                .send()
+                .await
+                .map_err(OutlookMailerError::SendMailRequest)?;
+
+            graph::receive_response(response).await?;
+
+            offset = end;
+        }
+
+        Ok(())
+    }
+}
+
+/// Microsoft Graph API JSON draft message response.
+#[derive(Debug, Deserialize)]
+struct Draft {
+    id: String,
+}
+
+/// Microsoft Graph API JSON upload session response.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UploadSession {
+    upload_url: String,
+}
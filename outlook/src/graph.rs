@@ -0,0 +1,414 @@
+//! Microsoft Graph API response handling.
+
+use std::time::{Duration, SystemTime};
+
+use reqwest::header::{HeaderMap, DATE, RETRY_AFTER};
+use reqwest::StatusCode;
+use serde::Deserialize;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, error};
+
+use crate::OutlookMailerError;
+
+/// Microsoft Graph API `request-id` response header.
+pub(crate) const REQUEST_ID: &str = "request-id";
+
+/// Microsoft Graph API `client-request-id` request and response header.
+pub(crate) const CLIENT_REQUEST_ID: &str = "client-request-id";
+
+/// Microsoft Graph API `return-client-request-id` request header,
+/// asking the Microsoft Graph API to echo the `client-request-id` in the response.
+pub(crate) const RETURN_CLIENT_REQUEST_ID: &str = "return-client-request-id";
+
+/// Error response of the Microsoft Graph API, returned as [`OutlookMailerError::Graph`].
+///
+/// Holds the HTTP status code and, if the response body could be parsed,
+/// the Microsoft Graph API error code and message.
+///
+/// See <https://learn.microsoft.com/en-us/graph/errors>
+#[derive(Clone, Debug, thiserror::Error)]
+#[error(
+    "Microsoft Graph API responded with status {status}: {code}: {message} (request-id: {request_id}, client-request-id: {client_request_id}, date: {date})",
+    code = .code.as_deref().unwrap_or("<no error code>"),
+    message = .message.as_deref().unwrap_or("<no error message>"),
+    request_id = .request_id.as_deref().unwrap_or("<none>"),
+    client_request_id = .client_request_id.as_deref().unwrap_or("<none>"),
+    date = .date.as_deref().unwrap_or("<none>"),
+)]
+#[non_exhaustive]
+pub struct OutlookGraphError {
+    /// HTTP status code of the response.
+    pub status: StatusCode,
+
+    /// Microsoft Graph API error code, e.g. `ErrorSendAsDenied` or `MailboxNotEnabledForRESTAPI`.
+    pub code: Option<String>,
+
+    /// Human-readable Microsoft Graph API error message.
+    pub message: Option<String>,
+
+    /// Microsoft Graph API `request-id`, identifying the request in support cases with Microsoft.
+    pub request_id: Option<String>,
+
+    /// Microsoft Graph API `client-request-id`, correlating the request with the client.
+    pub client_request_id: Option<String>,
+
+    /// Date and time of the error response, as reported by the Microsoft Graph API.
+    pub date: Option<String>,
+
+    /// Delay suggested by the Microsoft Graph API `Retry-After` response header
+    /// before retrying a throttled request.
+    pub retry_after: Option<Duration>,
+}
+
+impl OutlookGraphError {
+    /// Parse a Microsoft Graph API error response.
+    ///
+    /// The request id, client request id and date are taken from the `innerError` object of the response body,
+    /// falling back to the corresponding response headers.
+    pub(crate) fn from_response_parts(
+        status: StatusCode,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Self {
+        let error = serde_json::from_slice::<ErrorResponse>(body)
+            .map(|response| response.error)
+            .unwrap_or_default();
+        let inner_error = error.inner_error.unwrap_or_default();
+
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToString::to_string)
+        };
+
+        Self {
+            status,
+            code: error.code,
+            message: error.message,
+            request_id: inner_error.request_id.or_else(|| header(REQUEST_ID)),
+            client_request_id: inner_error
+                .client_request_id
+                .or_else(|| header(CLIENT_REQUEST_ID)),
+            date: inner_error.date.or_else(|| header(DATE.as_str())),
+            retry_after: retry_after(headers),
+        }
+    }
+}
+
+/// Whether the Microsoft Graph API throttled the request.
+///
+/// See <https://learn.microsoft.com/en-us/graph/throttling>
+pub(crate) fn is_throttled(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE
+    )
+}
+
+/// Whether a request failed with `408 Request Timeout`, `429 Too Many Requests` or a `5xx` server error,
+/// and may succeed if retried later.
+pub(crate) fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::REQUEST_TIMEOUT
+        || status == StatusCode::TOO_MANY_REQUESTS
+        || status.is_server_error()
+}
+
+/// Parse the `Retry-After` response header, given either as delay in seconds or as HTTP date.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let retry_after = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match retry_after.parse::<u64>() {
+        Ok(seconds) => Some(Duration::from_secs(seconds)),
+        Err(_) => httpdate::parse_http_date(retry_after)
+            .ok()
+            .map(|date| date.duration_since(SystemTime::now()).unwrap_or_default()),
+    }
+}
+
+/// Exponential backoff delay for a throttled response without `Retry-After` header.
+pub(crate) fn fallback_retry_after(retry: u32) -> Duration {
+    Duration::from_secs(1 << retry.min(6))
+}
+
+/// Receipt of a message sent via the Microsoft Graph API,
+/// returned by [`OutlookMailer::send_mail_with_options`](crate::OutlookMailer::send_mail_with_options).
+///
+/// Holds identifiers correlating the message with the Microsoft Graph API request,
+/// e.g. for support cases with Microsoft.
+#[derive(Clone, Debug, Default)]
+#[non_exhaustive]
+pub struct OutlookSendReceipt {
+    /// Microsoft Graph API `request-id` of the request which sent the message.
+    pub request_id: Option<String>,
+
+    /// Microsoft Graph API `client-request-id` of the request which sent the message.
+    ///
+    /// Either set by the caller through [`OutlookSendOptions::client_request_id`](crate::OutlookSendOptions::client_request_id),
+    /// or generated by the mailer.
+    pub client_request_id: Option<String>,
+
+    /// Microsoft Graph API message id of the draft message through which the message was sent.
+    ///
+    /// Only available for messages sent through a draft message, such as large messages.
+    /// The `sendMail` endpoints do not return a message id.
+    pub message_id: Option<String>,
+
+    /// The `Message-ID` header of the MIME message, if present.
+    ///
+    /// Messages converted into Microsoft Graph API JSON messages are sent with this `internetMessageId`.
+    pub internet_message_id: Option<String>,
+}
+
+impl OutlookSendReceipt {
+    /// Create a receipt from the headers of the Microsoft Graph API response to the request which sent the message.
+    pub(crate) fn from_response(response: &GraphResponse) -> Self {
+        Self {
+            request_id: response.header(REQUEST_ID),
+            client_request_id: response.header(CLIENT_REQUEST_ID),
+            ..Self::default()
+        }
+    }
+}
+
+/// Success response of the Microsoft Graph API.
+#[derive(Debug)]
+pub(crate) struct GraphResponse {
+    pub(crate) headers: HeaderMap,
+    pub(crate) body: Vec<u8>,
+}
+
+impl GraphResponse {
+    /// The value of the response header `name`, if present and valid UTF-8.
+    pub(crate) fn header(&self, name: &str) -> Option<String> {
+        self.headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(ToString::to_string)
+    }
+}
+
+/// Receive the Microsoft Graph API response,
+/// returning an [`OutlookMailerError::Graph`] error if the response status is not a success.
+///
+/// Returns the response headers and body of a success response.
+///
+/// # Errors
+///
+/// Returns an [`OutlookMailerError::SendMailResponseBody`] error if the response body cannot be received.
+///
+/// Returns an [`OutlookMailerError::Graph`] error if the response status is not a success.
+pub(crate) async fn receive_response(
+    response: reqwest::Response,
+) -> Result<GraphResponse, OutlookMailerError> {
+    let status = response.status();
+    let headers = response.headers().clone();
+
+    #[cfg(feature = "tracing")]
+    if status.is_success() {
+        debug!(?response);
+    } else {
+        error!(?response);
+    }
+
+    let body = response
+        .bytes()
+        .await
+        .map_err(OutlookMailerError::SendMailResponseBody)?;
+
+    #[cfg(feature = "tracing")]
+    {
+        // Log the response JSON as plain text.
+        let response_text = String::from_utf8_lossy(&body);
+        if status.is_success() {
+            debug!(%response_text);
+        } else {
+            error!(%response_text);
+        }
+    }
+
+    if !status.is_success() {
+        return Err(OutlookMailerError::Graph(Box::new(
+            OutlookGraphError::from_response_parts(status, &headers, &body),
+        )));
+    }
+
+    Ok(GraphResponse {
+        headers,
+        body: body.into(),
+    })
+}
+
+/// Microsoft Graph API JSON error response.
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+/// Microsoft Graph API JSON error object.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ErrorBody {
+    code: Option<String>,
+    message: Option<String>,
+    inner_error: Option<InnerError>,
+}
+
+/// Microsoft Graph API JSON inner error object.
+#[derive(Debug, Default, Deserialize)]
+struct InnerError {
+    #[serde(rename = "request-id")]
+    request_id: Option<String>,
+    #[serde(rename = "client-request-id")]
+    client_request_id: Option<String>,
+    date: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::header::{HeaderName, HeaderValue};
+
+    use super::*;
+
+    fn headers(retry_after: &str) -> HeaderMap {
+        HeaderMap::from_iter([(RETRY_AFTER, HeaderValue::from_str(retry_after).unwrap())])
+    }
+
+    fn response_headers(headers: &[(&'static str, &str)]) -> HeaderMap {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    HeaderName::from_static(name),
+                    HeaderValue::from_str(value).unwrap(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_graph_error_response_body() {
+        let body = br#"{
+            "error": {
+                "code": "ErrorSendAsDenied",
+                "message": "The user account which was used to submit this request does not have the right to send mail on behalf of the specified sending account.",
+                "innerError": {
+                    "date": "2026-10-16T08:15:42",
+                    "request-id": "8f6a3c2e-1d4b-4f0a-9b7e-2c5d8e1f3a60",
+                    "client-request-id": "4e2d1c0b-9a8f-4e7d-8c6b-5a4f3e2d1c0b"
+                }
+            }
+        }"#;
+        let headers = response_headers(&[
+            ("request-id", "header-request-id"),
+            ("client-request-id", "header-client-request-id"),
+            ("date", "Fri, 16 Oct 2026 08:15:42 GMT"),
+        ]);
+
+        let error = OutlookGraphError::from_response_parts(StatusCode::FORBIDDEN, &headers, body);
+
+        assert_eq!(error.status, StatusCode::FORBIDDEN);
+        assert_eq!(error.code.as_deref(), Some("ErrorSendAsDenied"));
+        assert!(error
+            .message
+            .as_deref()
+            .is_some_and(|message| message.starts_with("The user account")));
+        // The inner error takes precedence over the response headers.
+        assert_eq!(
+            error.request_id.as_deref(),
+            Some("8f6a3c2e-1d4b-4f0a-9b7e-2c5d8e1f3a60")
+        );
+        assert_eq!(
+            error.client_request_id.as_deref(),
+            Some("4e2d1c0b-9a8f-4e7d-8c6b-5a4f3e2d1c0b")
+        );
+        assert_eq!(error.date.as_deref(), Some("2026-10-16T08:15:42"));
+        assert_eq!(error.retry_after, None);
+    }
+
+    #[test]
+    fn falls_back_to_response_headers_without_inner_error() {
+        let body = br#"{"error":{"code":"ApplicationThrottled","message":"Application is over its MailboxConcurrency limit."}}"#;
+        let headers = response_headers(&[
+            ("request-id", "header-request-id"),
+            ("client-request-id", "header-client-request-id"),
+            ("date", "Fri, 16 Oct 2026 08:15:42 GMT"),
+            ("retry-after", "10"),
+        ]);
+
+        let error =
+            OutlookGraphError::from_response_parts(StatusCode::TOO_MANY_REQUESTS, &headers, body);
+
+        assert_eq!(error.code.as_deref(), Some("ApplicationThrottled"));
+        assert_eq!(error.request_id.as_deref(), Some("header-request-id"));
+        assert_eq!(
+            error.client_request_id.as_deref(),
+            Some("header-client-request-id")
+        );
+        assert_eq!(error.date.as_deref(), Some("Fri, 16 Oct 2026 08:15:42 GMT"));
+        assert_eq!(error.retry_after, Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn tolerates_non_json_or_empty_error_response_body() {
+        let headers = response_headers(&[("request-id", "header-request-id")]);
+
+        for body in [b"<html><body>502 Bad Gateway</body></html>".as_slice(), b""] {
+            let error =
+                OutlookGraphError::from_response_parts(StatusCode::BAD_GATEWAY, &headers, body);
+
+            assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+            assert_eq!(error.code, None);
+            assert_eq!(error.message, None);
+            assert_eq!(error.request_id.as_deref(), Some("header-request-id"));
+            assert_eq!(error.client_request_id, None);
+            assert_eq!(
+                error.to_string(),
+                "Microsoft Graph API responded with status 502 Bad Gateway: <no error code>: <no error message> (request-id: header-request-id, client-request-id: <none>, date: <none>)"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_retry_after_delay_seconds() {
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers(" 5 ")), Some(Duration::from_secs(5)));
+        assert_eq!(retry_after(&headers("0")), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn parses_retry_after_http_date() {
+        let date = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(30));
+
+        let delay = retry_after(&headers(&date)).unwrap();
+
+        // HTTP dates have a resolution of one second.
+        assert!(delay > Duration::from_secs(28) && delay <= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn past_retry_after_http_date_is_no_delay() {
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn ignores_missing_or_invalid_retry_after() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("soon")), None);
+        assert_eq!(retry_after(&headers("-1")), None);
+    }
+
+    #[test]
+    fn fallback_retry_after_backs_off_exponentially() {
+        let delays: Vec<_> = (0..8).map(fallback_retry_after).collect();
+
+        assert_eq!(
+            delays,
+            [1, 2, 4, 8, 16, 32, 64, 64].map(Duration::from_secs)
+        );
+    }
+}
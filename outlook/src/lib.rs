@@ -94,29 +94,301 @@
 //! - `tracing`: Enable debug and error logging using the [`tracing`](https://docs.rs/crate/tracing) crate.
 //!   All relevant functions are instrumented.
 //!
+//! - `certificate`: Enable `OutlookCredentials::Certificate`,
+//!   authenticating with client assertion JWTs signed by an X.509 certificate private key.
+//!
 //! Default: `tracing`.
 //!
-//! ## Roadmap
+//! ## Configuring endpoints
+//!
+//! Use [`OutlookMailer::builder`] to target a national cloud (such as US Government or China)
+//! or a local mock server, by overriding the Microsoft Identity service authority host,
+//! the Microsoft Graph API base URL, API version and access token scopes:
+//!
+//! ```no_run
+//! # async fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_outlook::OutlookMailer;
+//! let mailer = OutlookMailer::builder(
+//!     "<Microsoft Identity service tenant>".into(),
+//!     "<OAuth2 app GUID>".into(),
+//!     secrecy::SecretString::from("<OAuth2 app secret>")
+//! )
+//! .authority_host("https://login.microsoftonline.us")
+//! .graph_base_url("https://graph.microsoft.us")
+//! .build()
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## HTTP client
+//!
+//! By default, the [`OutlookMailer`] builds its own HTTP client without timeouts.
+//! Configure it with [`OutlookMailerBuilder::connect_timeout`], [`OutlookMailerBuilder::timeout`],
+//! [`OutlookMailerBuilder::user_agent`] and [`OutlookMailerBuilder::root_certificate`],
+//! or supply a preconfigured `reqwest::Client`, e.g. with a corporate proxy,
+//! using [`OutlookMailerBuilder::http_client`].
+//!
+//! ## Certificate credentials
+//!
+//! Instead of a client secret, the OAuth2 app may authenticate with a client assertion JWT,
+//! signed with the private key of an X.509 certificate registered with the app (crate feature `certificate`),
+//! or with a pre-built client assertion returned by an async callback:
+//!
+//! ```no_run
+//! # #[cfg(feature = "certificate")]
+//! # async fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_outlook::{OutlookClientCertificate, OutlookMailer};
+//! let certificate = OutlookClientCertificate::from_pem(
+//!     &std::fs::read("certificate.pem")?,
+//!     &secrecy::SecretString::from(std::fs::read_to_string("private-key.pem")?),
+//! )?;
+//!
+//! let mailer = OutlookMailer::builder(
+//!     "<Microsoft Identity service tenant>".into(),
+//!     "<OAuth2 app GUID>".into(),
+//!     certificate,
+//! )
+//! .build()
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Token providers
+//!
+//! Access tokens may also be retrieved from any [`TokenProvider`],
+//! such as a [`FederatedTokenFile`] for Kubernetes workload identity federation,
+//! or an [`FnTokenProvider`] wrapping an arbitrary async closure:
+//!
+//! ```no_run
+//! # async fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_outlook::{FederatedTokenFile, OutlookMailer};
+//! let mailer = OutlookMailer::builder_with_token_provider(
+//!     "<Microsoft Identity service tenant>".into(),
+//!     "<OAuth2 app GUID>".into(),
+//!     FederatedTokenFile::from_env().ok_or("AZURE_FEDERATED_TOKEN_FILE is not set")?,
+//! )
+//! .build()
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Shared mailboxes
+//!
+//! By default, each message is sent from the mailbox of its envelope sender.
+//! Use [`OutlookMailerBuilder::mailbox`] to send from a shared or service mailbox,
+//! or [`OutlookMailer::send_mail_with_options`] to choose the mailbox per message,
+//! independently of the `From:` header:
+//!
+//! ```no_run
+//! # async fn test(message: async_mailer_core::mail_send::smtp::message::Message<'_>) -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_outlook::{OutlookMailbox, OutlookMailer, OutlookSendOptions};
+//! let mailer = OutlookMailer::builder(
+//!     "<Microsoft Identity service tenant>".into(),
+//!     "<OAuth2 app GUID>".into(),
+//!     secrecy::SecretString::from("<OAuth2 app secret>")
+//! )
+//! .mailbox(OutlookMailbox::user("noreply@example.com"))
+//! .build()
+//! .await?;
+//!
+//! mailer
+//!     .send_mail_with_options(
+//!         message,
+//!         &OutlookSendOptions::new().mailbox(OutlookMailbox::user("billing@example.com")),
+//!     )
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Lazy construction
+//!
+//! [`OutlookMailer::new`] and [`OutlookMailerBuilder::build`] retrieve an access token,
+//! failing early on invalid credentials. To construct the mailer in synchronous code,
+//! or to start up while the Microsoft Identity service is unreachable,
+//! use [`OutlookMailer::new_lazy`] or [`OutlookMailerBuilder::build_lazy`] instead.
+//! These only validate the configuration, deferring the access token request to the first message sent.
+//! Call [`OutlookMailer::warm_up`] to retrieve the access token ahead of time, or as a health check.
+//!
+//! ## Delegated authentication
+//!
+//! To send as the signed-in user instead of through an app registration with the `Mail.Send` application permission,
+//! use a [`DelegatedTokenProvider`]. It refreshes access tokens with a refresh token held by a [`RefreshTokenStore`],
+//! saving each rotated refresh token back to the store. If no refresh token is stored,
+//! the user may be signed in interactively with the OAuth2 device code flow:
+//!
+//! ```no_run
+//! # async fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_outlook::{DelegatedTokenProvider, InMemoryRefreshTokenStore, OutlookMailbox, OutlookMailer};
+//! let token_provider = DelegatedTokenProvider::new(InMemoryRefreshTokenStore::default())
+//!     .device_code_prompt(|prompt| eprintln!("{}", prompt.message));
+//!
+//! let mailer = OutlookMailer::builder_with_token_provider(
+//!     "<Microsoft Identity service tenant>".into(),
+//!     "<OAuth2 app GUID>".into(),
+//!     token_provider,
+//! )
+//! .scopes(["https://graph.microsoft.com/Mail.Send"])
+//! .mailbox(OutlookMailbox::Me)
+//! .build()
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Send receipts
+//!
+//! [`OutlookMailer::send_mail_with_options`] returns an [`OutlookSendReceipt`],
+//! holding the Microsoft Graph API `request-id` and `client-request-id` of the request which sent the message,
+//! for correlation in support cases with Microsoft. Set your own client request id
+//! with [`OutlookSendOptions::client_request_id`]; otherwise a random UUID is generated per message.
+//!
+//! ## Batch sending
+//!
+//! Use [`OutlookMailer::send_mail_batch`] to send many messages with few requests,
+//! grouping up to 20 messages into each Microsoft Graph API `$batch` request,
+//! and up to 4 messages per sending mailbox, as Outlook limits concurrent requests per mailbox.
+//! One result is returned per message.
+//!
+//! # Throttling
+//!
+//! If the Microsoft Graph API throttles a request with `429 Too Many Requests` or `503 Service Unavailable`,
+//! the [`OutlookMailer`] waits for the delay suggested by the `Retry-After` response header and retries the request.
+//! The retry budget is configured with [`OutlookMailerBuilder::max_throttle_retries`]
+//! and [`OutlookMailerBuilder::max_retry_after`].
+//! Once the budget is exhausted, the suggested delay is available via [`OutlookMailerError::retry_after`].
+//!
+//! # Large messages
+//!
+//! The Microsoft Graph API MIME `sendMail` endpoint limits the base64-encoded message to 4 MB.
+//! Larger messages are transparently sent by creating a draft message, adding its attachments,
+//! uploading attachments of 3 MB or more in chunks through an upload session, and sending the draft.
+//! If this fails, the draft is deleted.
+//! Messages exceeding the limit even without their attachments, e.g. because of a large body,
+//! are rejected up front with an [`OutlookMailerError::MessageTooLarge`] error.
+//! Draft messages are converted from the MIME message, see [JSON message conversion](#json-message-conversion).
+//!
+//! This requires the `Mail.ReadWrite` application permission in addition to `Mail.Send`.
+//!
+//! # Sent Items and drafts
 //!
-//! Access token auto-refresh is planned to be implemented on the [`OutlookMailer`].
+//! By default, the Microsoft Graph API saves a copy of each sent message to the Sent Items folder.
+//! Disable this with [`OutlookMailerBuilder::save_to_sent_items`], or per message with
+//! [`OutlookSendOptions::save_to_sent_items`]. Such messages are converted and sent through the JSON `sendMail`
+//! endpoint with `saveToSentItems=false`, as the MIME `sendMail` endpoint does not support the option.
+//!
+//! Use [`OutlookMailer::create_draft`] to create a draft message without sending it,
+//! returning the Microsoft Graph API message id.
+//!
+//! # JSON message conversion
+//!
+//! Large messages, messages not saved to the Sent Items folder, batched messages ([`OutlookMailer::send_mail_batch`])
+//! and drafts ([`OutlookMailer::create_draft`]) cannot be sent through the MIME `sendMail` endpoint.
+//! Such messages are parsed and converted into Microsoft Graph API JSON messages, retaining the subject,
+//! sender, recipients, `Reply-To` addresses, body and attachments, as well as the following headers:
+//!
+//! - `Message-ID`, as `internetMessageId`.
+//! - `In-Reply-To` and `References`, as the MAPI properties `PidTagInReplyToId` and `PidTagInternetReferences`.
+//! - Custom `X-` headers, as `internetMessageHeaders`.
+//!
+//! The conversion is lossy:
+//!
+//! - A JSON message has a single body. If the MIME message has an HTML body, its plain text alternative is dropped.
+//! - All other headers, such as `Date`, `Sender` or `List-Unsubscribe`, are dropped,
+//!   as the Microsoft Graph API only accepts custom `X-` headers.
+//! - The MIME structure of the sent message is rebuilt by Exchange Online,
+//!   so signatures over the original message, e.g. S/MIME, do not survive the conversion.
+//!
+//! Send messages which must be delivered as-is below 4 MB, and saved to the Sent Items folder.
+//!
+//! # Access token refresh
+//!
+//! The [`OutlookMailer`] caches its Microsoft Graph API access token and refreshes it automatically:
+//! proactively, shortly before the token expires, and reactively, once, if the Microsoft Graph API
+//! rejects the token with `401 Unauthorized`. Concurrent senders share a single in-flight token request.
+//! Clones of an [`OutlookMailer`] share the same token cache.
+//!
+//! Before requesting a new access token, the [`OutlookMailer`] consults its [`TokenCache`].
+//! Configure a [`FileTokenCache`] with [`OutlookMailerBuilder::token_cache`]
+//! to reuse access tokens across process restarts.
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use base64::{engine::general_purpose::STANDARD as base64_engine, Engine as _};
-use reqwest::header::{HeaderMap, AUTHORIZATION, CONTENT_TYPE};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::StatusCode;
 use secrecy::{ExposeSecret, SecretString};
-use serde::Deserialize;
+use tokio::sync::Mutex;
 
 #[cfg(feature = "tracing")]
-use tracing::{debug, error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 use async_mailer_core::mail_send::smtp::message::Message;
-use async_mailer_core::{util, ArcMailer, BoxMailer, DynMailer, DynMailerError, Mailer};
+use async_mailer_core::{ArcMailer, BoxMailer, DynMailer, DynMailerError, Mailer};
+
+#[cfg(feature = "tracing")]
+use async_mailer_core::util;
+
+use graph::GraphResponse;
+use message::GraphMessage;
+
+mod batch;
+mod cache;
+mod credentials;
+mod delegated;
+mod draft;
+mod graph;
+mod message;
+mod options;
+mod token;
+
+pub use cache::{FileTokenCache, InMemoryTokenCache, TokenCache, TokenCacheError};
+pub use credentials::{
+    ClientAssertionCallback, ClientAssertionCallbackError, ClientAssertionFuture,
+    OutlookCredentials,
+};
+pub use delegated::{
+    DelegatedTokenProvider, DeviceCodePrompt, DeviceCodePromptCallback, InMemoryRefreshTokenStore,
+    RefreshTokenStore, RefreshTokenStoreError,
+};
+pub use graph::{OutlookGraphError, OutlookSendReceipt};
+pub use options::{OutlookMailbox, OutlookSendOptions};
+pub use token::{
+    FederatedTokenFile, FnTokenProvider, OutlookAccessToken, TokenProvider, TokenProviderError,
+    TokenRequest,
+};
+
+#[cfg(feature = "certificate")]
+pub use credentials::{OutlookCertificateError, OutlookClientCertificate};
 
 /// Error returned by [`OutlookMailer::new`] and [`OutlookMailer::send_mail`].
 #[derive(Debug, thiserror::Error)]
 pub enum OutlookMailerError {
+    /// Failed to build the HTTP client.
+    #[error("failed to build the HTTP client: {0}")]
+    BuildHttpClient(reqwest::Error),
+
+    /// The Microsoft Graph API `$batch` request carrying the message failed.
+    ///
+    /// The wrapped error is shared by all messages of the batch.
+    #[error("Microsoft Graph API batch request failed: {0}")]
+    Batch(Arc<OutlookMailerError>),
+
+    /// The Microsoft Graph API `$batch` response holds no sub-response for the message.
+    ///
+    /// It is unknown whether the message was sent.
+    #[error("Microsoft Graph API batch response holds no response for the message")]
+    MissingBatchResponse,
+
+    /// Invalid Microsoft Identity service or Microsoft Graph API URL.
+    #[error("invalid Microsoft Identity service or Microsoft Graph API URL: {0}")]
+    InvalidUrl(url::ParseError),
+
     /// Failed to retrieve Microsoft Graph API access token.
     #[error("failed to retrieve Microsoft Graph API access token: {0}")]
     RetrieveAccessToken(#[from] OutlookAccessTokenError),
@@ -127,15 +399,78 @@ pub enum OutlookMailerError {
     )]
     SendMailRequest(reqwest::Error),
 
-    /// Failed sending Outlook MIME mail through Microsoft Graph API.
+    /// Microsoft Graph API responded with an error.
+    ///
+    /// The wrapped [`OutlookGraphError`] holds the HTTP status code,
+    /// as well as the Microsoft Graph API error code, message, request id, client request id and date.
     #[error("failed sending Outlook MIME mail through Microsoft Graph API: {0}")]
-    SendMailResponse(reqwest::Error),
+    Graph(Box<OutlookGraphError>),
 
     /// Failed retrieving response body from Microsoft Graph API.
-    /// (Crate feature `tracing` only.)
-    #[cfg(feature = "tracing")]
     #[error("failed retrieving response body from Microsoft Graph API: {0}")]
     SendMailResponseBody(reqwest::Error),
+
+    /// Failed to parse response body from Microsoft Graph API.
+    #[error("failed to parse response body from Microsoft Graph API: {0}")]
+    ParseResponse(serde_json::Error),
+
+    /// Failed to parse MIME message for conversion into a Microsoft Graph API JSON message,
+    /// as required to send large messages, to suppress Sent Items copies, or to create drafts.
+    #[error("failed to parse MIME message for conversion into Microsoft Graph API JSON message")]
+    ParseMessage,
+
+    /// The message exceeds the 4 MB Microsoft Graph API request size limit even without its attachments,
+    /// e.g. because of a large body, so that it cannot be sent or created as a draft message.
+    #[error("message of {0} bytes without attachments exceeds the 4 MB Microsoft Graph API request size limit")]
+    MessageTooLarge(usize),
+}
+
+impl OutlookMailerError {
+    /// The delay suggested by the Microsoft Graph API before retrying a throttled request.
+    ///
+    /// Returns `Some` if the Microsoft Graph API responded with a `Retry-After` header,
+    /// e.g. because the request was throttled, and the throttling retry budget was exhausted.
+    /// Otherwise returns `None`.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Graph(error) => error.retry_after,
+            Self::Batch(error) => error.retry_after(),
+            _ => None,
+        }
+    }
+
+    /// Whether sending the message may succeed if retried later.
+    ///
+    /// Transient errors are Microsoft Graph API responses with status `408 Request Timeout`,
+    /// `429 Too Many Requests` or a `5xx` server error, network errors while sending the request
+    /// or receiving the response, and network errors, Microsoft Identity service responses with any of these status codes,
+    /// or custom token provider and client assertion callback failures while retrieving an access token.
+    ///
+    /// All other errors are permanent, see [`OutlookMailerError::is_permanent`].
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Graph(error) => graph::is_transient_status(error.status),
+            Self::Batch(error) => error.is_transient(),
+            Self::SendMailRequest(_) | Self::SendMailResponseBody(_) => true,
+            Self::RetrieveAccessToken(error) => match error {
+                OutlookAccessTokenError::HttpStatus(status) => graph::is_transient_status(*status),
+                OutlookAccessTokenError::SendRequest(_)
+                | OutlookAccessTokenError::ReceiveResponse(_)
+                | OutlookAccessTokenError::ClientAssertionCallback(_)
+                | OutlookAccessTokenError::TokenProvider(_) => true,
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether sending the message will fail again if retried unchanged,
+    /// e.g. because the Microsoft Graph API rejected the message or the credentials.
+    ///
+    /// Every error is either transient or permanent.
+    pub fn is_permanent(&self) -> bool {
+        !self.is_transient()
+    }
 }
 
 /// Error returned by [`OutlookMailer::new`] if an access token cannot be retrieved.
@@ -149,9 +484,49 @@ pub enum OutlookAccessTokenError {
     #[error("failed receiving OAuth2 client credentials grant access token response from Microsoft Identity service: {0}")]
     ReceiveResponse(reqwest::Error),
 
+    /// Microsoft Identity service responded with a non-success HTTP status code,
+    /// e.g. `429 Too Many Requests` or `503 Service Unavailable`, without an OAuth2 error to report.
+    #[error("Microsoft Identity service responded with HTTP status {0}")]
+    HttpStatus(StatusCode),
+
     /// Failed to parse OAuth2 client credentials grant access token response from Microsoft Identity service.
     #[error("failed to parse OAuth2 client credentials grant access token response from Microsoft Identity service: {0}")]
     ParseResponse(serde_json::Error),
+
+    /// Failed to sign OAuth2 client assertion JWT with the certificate private key.
+    /// (Crate feature `certificate` only.)
+    #[cfg(feature = "certificate")]
+    #[error("failed to sign OAuth2 client assertion JWT with the certificate private key: {0}")]
+    SignClientAssertion(jsonwebtoken::errors::Error),
+
+    /// Failed to retrieve OAuth2 client assertion JWT from the client assertion callback.
+    #[error(
+        "failed to retrieve OAuth2 client assertion JWT from the client assertion callback: {0}"
+    )]
+    ClientAssertionCallback(ClientAssertionCallbackError),
+
+    /// Failed to read federated token file.
+    #[error("failed to read federated token file: {0}")]
+    ReadFederatedTokenFile(std::io::Error),
+
+    /// Failed to retrieve access token from custom token provider.
+    #[error("failed to retrieve access token from custom token provider: {0}")]
+    TokenProvider(TokenProviderError),
+
+    /// Microsoft Identity service responded with an OAuth2 error,
+    /// e.g. because a refresh token was revoked, or the user declined a device code sign-in.
+    #[error("Microsoft Identity service responded with OAuth2 error: {0}")]
+    OAuth2(String),
+
+    /// No refresh token is stored, and no device code prompt is configured to sign the user in.
+    #[error(
+        "no refresh token is stored, and no device code prompt is configured to sign the user in"
+    )]
+    MissingRefreshToken,
+
+    /// Failed to load or store refresh token.
+    #[error("failed to load or store refresh token: {0}")]
+    RefreshTokenStore(RefreshTokenStoreError),
 }
 
 /// An Outlook mailer client, implementing the [`async_mailer_core::Mailer`](https://docs.rs/async-mailer/latest/async_mailer/trait.Mailer.html)
@@ -159,15 +534,76 @@ pub enum OutlookAccessTokenError {
 /// to be used as generic mailer or runtime-pluggable trait object.
 ///
 /// Sends mail authenticated by OAuth2 client credentials grant via the Microsoft Graph API.
+/// Alternatively, access tokens may be retrieved from a custom [`TokenProvider`].
+///
+/// The access token is cached and refreshed automatically before it expires.
+/// Clones share the same token cache.
+///
+/// Messages exceeding the 4 MB limit of the MIME `sendMail` endpoint are sent as draft messages,
+/// uploading large attachments in chunks.
 #[derive(Clone, Debug)]
 pub struct OutlookMailer {
     http_client: reqwest::Client,
-    access_token: SecretString,
+    app_guid: String,
+    token_provider: Arc<dyn TokenProvider>,
+    token_url: String,
+    scope: String,
+    graph_url: String,
+    max_throttle_retries: u32,
+    max_retry_after: Duration,
+    mailbox: OutlookMailbox,
+    save_to_sent_items: bool,
+    token_cache: Arc<dyn TokenCache>,
+    access_token: Arc<Mutex<Option<OutlookAccessToken>>>,
+    token_request: Arc<Mutex<()>>,
 }
 
+/// Default Microsoft Identity service authority host of the Microsoft global cloud.
+const DEFAULT_AUTHORITY_HOST: &str = "https://login.microsoftonline.com";
+
+/// Default Microsoft Graph API base URL of the Microsoft global cloud.
+const DEFAULT_GRAPH_BASE_URL: &str = "https://graph.microsoft.com";
+
+/// Default Microsoft Graph API version.
+const DEFAULT_GRAPH_API_VERSION: &str = "v1.0";
+
+/// Default number of retries of requests throttled by the Microsoft Graph API.
+const DEFAULT_MAX_THROTTLE_RETRIES: u32 = 3;
+
+/// Default longest `Retry-After` delay to wait for before retrying a throttled request.
+const DEFAULT_MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
 impl OutlookMailer {
+    /// Create an [`OutlookMailerBuilder`] to configure a new Outlook mailer client.
+    ///
+    /// Use the builder to override the Microsoft Identity service and Microsoft Graph API endpoints,
+    /// e.g. for national clouds or for testing against a local mock server.
+    ///
+    /// Pass either a [`SecretString`] client secret or other [`OutlookCredentials`].
+    pub fn builder(
+        tenant: String,
+        app_guid: String,
+        credentials: impl Into<OutlookCredentials>,
+    ) -> OutlookMailerBuilder {
+        OutlookMailerBuilder::new(tenant, app_guid, credentials)
+    }
+
+    /// Create an [`OutlookMailerBuilder`] to configure a new Outlook mailer client,
+    /// retrieving access tokens from a custom [`TokenProvider`],
+    /// such as a [`FederatedTokenFile`] or an [`FnTokenProvider`].
+    pub fn builder_with_token_provider(
+        tenant: String,
+        app_guid: String,
+        token_provider: impl TokenProvider + 'static,
+    ) -> OutlookMailerBuilder {
+        OutlookMailerBuilder::with_token_provider(tenant, app_guid, token_provider)
+    }
+
     /// Create a new Outlook mailer client.
     ///
+    /// Uses the Microsoft global cloud endpoints.
+    /// Use [`OutlookMailer::builder`] to configure alternative endpoints.
+    ///
     /// # Errors
     ///
     /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error
@@ -175,6 +611,9 @@ impl OutlookMailer {
     ///
     /// - Wrapping an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
     /// - Wrapping an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    /// - Wrapping an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    ///   with a non-success HTTP status code.
+    /// - Wrapping an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request.
     /// - Wrapping an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
     #[cfg_attr(feature = "tracing", instrument)]
     pub async fn new(
@@ -182,16 +621,7 @@ impl OutlookMailer {
         app_guid: String,
         secret: SecretString,
     ) -> Result<Self, OutlookMailerError> {
-        let http_client = reqwest::Client::new();
-
-        let access_token = Self::get_access_token(&tenant, &app_guid, &secret, http_client.clone())
-            .await
-            .map_err(OutlookMailerError::RetrieveAccessToken)?;
-
-        Ok(Self {
-            http_client,
-            access_token,
-        })
+        Self::builder(tenant, app_guid, secret).build().await
     }
 
     /// Create a new Outlook mailer client as dynamic `async_mailer::BoxMailer`.
@@ -203,6 +633,9 @@ impl OutlookMailer {
     ///
     /// - Wrapping an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
     /// - Wrapping an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    /// - Wrapping an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    ///   with a non-success HTTP status code.
+    /// - Wrapping an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request.
     /// - Wrapping an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
     #[cfg_attr(feature = "tracing", instrument)]
     pub async fn new_box(
@@ -222,6 +655,9 @@ impl OutlookMailer {
     ///
     /// - Wrapping an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
     /// - Wrapping an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    /// - Wrapping an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    ///   with a non-success HTTP status code.
+    /// - Wrapping an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request.
     /// - Wrapping an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
     #[cfg_attr(feature = "tracing", instrument)]
     pub async fn new_arc(
@@ -232,76 +668,122 @@ impl OutlookMailer {
         Ok(Arc::new(Self::new(tenant, app_guid, secret).await?))
     }
 
-    /// Retrieve an OAuth2 client credentials grant access token from the Microsoft Identity service.
+    /// Create a new Outlook mailer client without retrieving an access token.
     ///
-    /// # Errors
+    /// The first access token is retrieved when the first message is sent,
+    /// or when calling [`OutlookMailer::warm_up`].
+    ///
+    /// Uses the Microsoft global cloud endpoints.
+    /// Use [`OutlookMailer::builder`] and [`OutlookMailerBuilder::build_lazy`] to configure alternative endpoints.
     ///
-    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    /// # Errors
     ///
-    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    /// Returns an [`OutlookMailerError::BuildHttpClient`] error if the HTTP client cannot be built.
     ///
-    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    /// Returns an [`OutlookMailerError::InvalidUrl`] error if the tenant does not form a valid URL.
     #[cfg_attr(feature = "tracing", instrument)]
-    async fn get_access_token(
-        tenant_id: &str,
-        client_id: &str,
-        client_secret: &SecretString,
-        http_client: reqwest::Client,
-    ) -> Result<SecretString, OutlookAccessTokenError> {
-        let token_url = format!("https://login.microsoftonline.com/{tenant_id}/oauth2/v2.0/token");
-
-        let form_data = [
-            ("client_id", client_id),
-            ("client_secret", client_secret.expose_secret()),
-            ("grant_type", "client_credentials"),
-            ("scope", &["https://graph.microsoft.com/.default"].join(" ")),
-        ];
-
-        let response = http_client
-            .post(&token_url)
-            .form(&form_data)
-            .send()
-            .await
-            .map_err(OutlookAccessTokenError::SendRequest)?;
-
-        let response_data = response
-            .bytes()
-            .await
-            .map_err(OutlookAccessTokenError::ReceiveResponse)?;
+    pub fn new_lazy(
+        tenant: String,
+        app_guid: String,
+        secret: SecretString,
+    ) -> Result<Self, OutlookMailerError> {
+        Self::builder(tenant, app_guid, secret).build_lazy()
+    }
 
-        let token_response: TokenResponse = serde_json::from_slice(&response_data)
-            .map_err(OutlookAccessTokenError::ParseResponse)?;
+    /// Create a new Outlook mailer client as dynamic `async_mailer::BoxMailer`,
+    /// without retrieving an access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::BuildHttpClient`] error if the HTTP client cannot be built.
+    ///
+    /// Returns an [`OutlookMailerError::InvalidUrl`] error if the tenant does not form a valid URL.
+    #[cfg_attr(feature = "tracing", instrument)]
+    pub fn new_box_lazy(
+        tenant: String,
+        app_guid: String,
+        secret: SecretString,
+    ) -> Result<BoxMailer, OutlookMailerError> {
+        Ok(Box::new(Self::new_lazy(tenant, app_guid, secret)?))
+    }
 
-        Ok(SecretString::from(token_response.access_token))
+    /// Create a new Outlook mailer client as dynamic `async_mailer::ArcMailer`,
+    /// without retrieving an access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::BuildHttpClient`] error if the HTTP client cannot be built.
+    ///
+    /// Returns an [`OutlookMailerError::InvalidUrl`] error if the tenant does not form a valid URL.
+    #[cfg_attr(feature = "tracing", instrument)]
+    pub fn new_arc_lazy(
+        tenant: String,
+        app_guid: String,
+        secret: SecretString,
+    ) -> Result<ArcMailer, OutlookMailerError> {
+        Ok(Arc::new(Self::new_lazy(tenant, app_guid, secret)?))
     }
-}
 
-// == Mailer ==
+    /// Retrieve an access token, unless a valid one is cached.
+    ///
+    /// Use this to acquire the first access token of a lazily built mailer ahead of the first message,
+    /// or as a health check of the configured credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error
+    /// if no valid access token is cached, and a new access token cannot be retrieved.
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    pub async fn warm_up(&self) -> Result<(), OutlookMailerError> {
+        self.get_access_token()
+            .await
+            .map_err(OutlookMailerError::RetrieveAccessToken)?;
 
-#[async_trait]
-impl Mailer for OutlookMailer {
-    type Error = OutlookMailerError;
+        Ok(())
+    }
 
-    /// Send the prepared MIME message via the Microsoft Graph API.
-    /// Statically typed [`Mailer`] implementation for direct
-    /// or generic (`impl Mailer` / `<M: Mailer>`) invocation without vtable dispatch.
+    /// Send the prepared MIME message via the Microsoft Graph API, applying per-message [`OutlookSendOptions`].
+    ///
+    /// Options which are not set fall back to the mailer's configuration.
+    /// E.g., use [`OutlookSendOptions::mailbox`] to send an individual message from a shared mailbox.
+    ///
+    /// Returns an [`OutlookSendReceipt`], holding the Microsoft Graph API `request-id` and `client-request-id`
+    /// of the request which sent the message, for correlation in support cases with Microsoft.
     ///
     /// # Errors
     ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if the cached access token
+    /// needs to be refreshed, but a new access token cannot be retrieved.
+    ///
     /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending the mailing request to the
     /// Microsoft Graph API fails.
     ///
-    /// Returns an [`OutlookMailerError::SendMailResponse`] error if the Microsoft Graph API responds
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
     /// with a non-success HTTP status code.
     ///
     /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if the Microsoft Graph API reponse body
     /// cannot be received.
-    /// (Crate feature `tracing` only: The response body is only received for logging.)
-    async fn send_mail(&self, message: Message<'_>) -> Result<(), Self::Error> {
-        // TODO: Token auto-refresh.
-
-        // Extract sender address necessary for Microsoft Graph API call.
-        let from_address = message.mail_from.email.to_string();
+    ///
+    /// Returns an [`OutlookMailerError::ParseMessage`] error if a message which cannot be sent through
+    /// the MIME `sendMail` endpoint cannot be parsed for conversion into a JSON message.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a Microsoft Graph API draft message
+    /// or upload session response cannot be parsed.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if a message exceeds the request size limit
+    /// without its attachments.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, message)))]
+    pub async fn send_mail_with_options(
+        &self,
+        message: Message<'_>,
+        options: &OutlookSendOptions,
+    ) -> Result<OutlookSendReceipt, OutlookMailerError> {
+        // Resolve the mailbox to send from, defaulting to the envelope sender.
+        let mailbox_path = options
+            .mailbox
+            .as_ref()
+            .unwrap_or(&self.mailbox)
+            .path(&message);
 
         #[cfg(feature = "tracing")]
         // Extract recipient addresses for tracing log output.
@@ -312,72 +794,702 @@ impl Mailer for OutlookMailer {
             recipient_addresses
         };
 
+        let save_to_sent_items = options
+            .save_to_sent_items
+            .unwrap_or(self.save_to_sent_items);
+
+        // Correlate all requests sending this message, using the caller's or a generated client request id.
+        let client_request_id = options
+            .client_request_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
         // Encode the message body according to the MIME-mail API endpoint documentation:
         // https://learn.microsoft.com/en-us/graph/api/user-sendmail?view=graph-rest-1.0&tabs=http#example-4-send-a-new-message-using-mime-format
         // See also https://learn.microsoft.com/en-us/graph/outlook-send-mime-message
         let message_base64 = base64_engine.encode(&message.body);
 
-        // Prepare the authorization header with OAuth 2.0 client credentials grant bearer token.
-        let mut headers = HeaderMap::new();
-        headers.insert(
-            AUTHORIZATION,
-            format!("Bearer {}", self.access_token.expose_secret())
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(CONTENT_TYPE, "text/plain".parse().unwrap());
-
         // Send the mail via Graph API.
-        let response = self
-            .http_client
-            .post(format!(
-                "https://graph.microsoft.com/v1.0/users/{from_address}/sendMail",
-            ))
-            .headers(headers)
-            .body(message_base64)
-            .send()
+        let result = if save_to_sent_items && message_base64.len() <= draft::MAX_SEND_MAIL_SIZE {
+            self.send_graph_request(Some(&client_request_id), |http_client| {
+                http_client
+                    .post(format!(
+                        "{graph_url}/{mailbox_path}/sendMail",
+                        graph_url = self.graph_url
+                    ))
+                    .header(CONTENT_TYPE, "text/plain")
+                    .body(message_base64.clone())
+            })
             .await
-            .map_err(OutlookMailerError::SendMailRequest)?;
+            .map(|response| OutlookSendReceipt::from_response(&response))
+        } else {
+            // The MIME `sendMail` endpoint neither supports `saveToSentItems`, nor large messages.
+            // Send through the JSON `sendMail` endpoint or a draft message instead.
+            #[cfg(feature = "tracing")]
+            info!(
+                size = message_base64.len(),
+                save_to_sent_items, "Sending Outlook mail as JSON message..."
+            );
+
+            match GraphMessage::parse(&message) {
+                Some(graph_message) => {
+                    self.send_graph_message(
+                        &mailbox_path,
+                        graph_message,
+                        save_to_sent_items,
+                        &client_request_id,
+                    )
+                    .await
+                }
+                None => Err(OutlookMailerError::ParseMessage),
+            }
+        };
+
+        #[cfg(feature = "tracing")]
+        match &result {
+            Ok(receipt) => info!(
+                request_id = receipt.request_id,
+                client_request_id = receipt.client_request_id,
+                "Sent Outlook mail to {recipient_addresses}"
+            ),
+            Err(error) => error!(
+                ?error,
+                client_request_id, "Failed to send Outlook mail to {recipient_addresses}"
+            ),
+        }
+
+        Ok(OutlookSendReceipt {
+            internet_message_id: message::internet_message_id(&message),
+            ..result?
+        })
+    }
+
+    /// Create a draft message from the prepared MIME message via the Microsoft Graph API, without sending it.
+    ///
+    /// The draft is created in the mailbox configured by [`OutlookSendOptions::mailbox`]
+    /// or [`OutlookMailerBuilder::mailbox`]. Attachments are added to the draft,
+    /// uploading large attachments in chunks.
+    /// The draft is converted from the MIME message, see [JSON message conversion](crate#json-message-conversion).
+    ///
+    /// Returns the Microsoft Graph API message id of the draft,
+    /// which may be used to review, update or send the draft later.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::ParseMessage`] error if the MIME message cannot be parsed.
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if the cached access token
+    /// needs to be refreshed, but a new access token cannot be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending a request to the
+    /// Microsoft Graph API fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if the Microsoft Graph API reponse body
+    /// cannot be received.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a Microsoft Graph API draft message
+    /// or upload session response cannot be parsed.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if a message exceeds the request size limit
+    /// without its attachments.
+    #[cfg_attr(feature = "tracing", instrument(skip(self, message)))]
+    pub async fn create_draft(
+        &self,
+        message: Message<'_>,
+        options: &OutlookSendOptions,
+    ) -> Result<String, OutlookMailerError> {
+        let mailbox_path = options
+            .mailbox
+            .as_ref()
+            .unwrap_or(&self.mailbox)
+            .path(&message);
+
+        let mut graph_message =
+            GraphMessage::parse(&message).ok_or(OutlookMailerError::ParseMessage)?;
 
+        // Apply to the draft, so that it is not saved to Sent Items once it is sent.
+        if !options
+            .save_to_sent_items
+            .unwrap_or(self.save_to_sent_items)
         {
-            // Get result with empty ok or status code error
-            // before moving `response` to consume the body.
-            let success = response
-                .error_for_status_ref()
-                // Un-reference `response`, so we can move out of it with `response.text()`.
-                .map(|_| {});
+            graph_message.delete_after_submit();
+        }
+
+        self.create_draft_message(
+            &mailbox_path,
+            &graph_message,
+            options.client_request_id.as_deref(),
+        )
+        .await
+    }
+
+    /// Get a valid access token, either from the cache or by requesting a new one.
+    ///
+    /// The mailer's own cache is consulted first, then the configured [`TokenCache`],
+    /// and only then is a new access token requested from the [`TokenProvider`].
+    ///
+    /// Token requests are serialized, so that concurrent senders wait for, and share,
+    /// a single in-flight token request. The cached token itself is only locked briefly,
+    /// and not while waiting for a token request, which may take long,
+    /// e.g. while a user signs in with the device code flow.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError`] if a new access token is required
+    /// but cannot be retrieved from the [`TokenProvider`].
+    #[cfg_attr(feature = "tracing", instrument(skip(self)))]
+    async fn get_access_token(&self) -> Result<SecretString, OutlookAccessTokenError> {
+        if let Some(secret) = self.cached_access_token().await {
+            return Ok(secret);
+        }
+
+        let _token_request = self.token_request.lock().await;
+
+        // Another sender may have retrieved a new access token while waiting for its token request.
+        if let Some(secret) = self.cached_access_token().await {
+            return Ok(secret);
+        }
+
+        let token_cache_key = self.token_cache_key();
 
+        // Token cache errors are not fatal, falling back to requesting a new access token.
+        match self.token_cache.load(&token_cache_key).await {
+            Ok(Some(cached)) if !cached.needs_refresh() => {
+                #[cfg(feature = "tracing")]
+                debug!("Using Microsoft Graph API access token from token cache");
+
+                let secret = cached.secret().clone();
+                *self.access_token.lock().await = Some(cached);
+
+                return Ok(secret);
+            }
+            Ok(_) => {}
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            Err(error) => {
+                #[cfg(feature = "tracing")]
+                warn!(?error, "Failed to load access token from token cache");
+            }
+        }
+
+        #[cfg(feature = "tracing")]
+        debug!("Requesting new Microsoft Graph API access token...");
+
+        let token = self
+            .token_provider
+            .access_token(&TokenRequest {
+                http_client: &self.http_client,
+                token_url: &self.token_url,
+                client_id: &self.app_guid,
+                scope: &self.scope,
+            })
+            .await?;
+
+        #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+        if let Err(error) = self.token_cache.store(&token_cache_key, &token).await {
             #[cfg(feature = "tracing")]
-            {
-                match success {
-                    Ok(()) => {
-                        info!("Sent Outlook mail to {recipient_addresses}");
-                        debug!(?response);
-                    }
-
-                    Err(ref error) => {
-                        error!(
-                            ?error,
-                            "Failed to send Outlook mail to {recipient_addresses}"
-                        );
-                        error!(?response);
-                    }
-                };
-
-                // Log the response JSON as plain text.
-                let response_text = response
-                    .text()
-                    .await
-                    .map_err(OutlookMailerError::SendMailResponseBody)?;
-                match &success {
-                    Ok(_) => debug!(response_text),
-                    Err(_) => error!(response_text),
+            warn!(?error, "Failed to store access token in token cache");
+        }
+
+        let secret = token.secret().clone();
+        *self.access_token.lock().await = Some(token);
+
+        Ok(secret)
+    }
+
+    /// The cached access token, unless it needs to be refreshed.
+    async fn cached_access_token(&self) -> Option<SecretString> {
+        self.access_token
+            .lock()
+            .await
+            .as_ref()
+            .filter(|token| !token.needs_refresh())
+            .map(|token| token.secret().clone())
+    }
+
+    /// Discard the cached access token after it was rejected by the Microsoft Graph API.
+    ///
+    /// The cache is only cleared if it still holds the rejected token,
+    /// so a token which was refreshed concurrently is retained.
+    async fn invalidate_access_token(&self, rejected: &SecretString) {
+        let mut access_token = self.access_token.lock().await;
+
+        if access_token
+            .as_ref()
+            .is_some_and(|cached| cached.secret().expose_secret() == rejected.expose_secret())
+        {
+            *access_token = None;
+
+            #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+            if let Err(error) = self.token_cache.remove(&self.token_cache_key()).await {
+                #[cfg(feature = "tracing")]
+                warn!(?error, "Failed to remove access token from token cache");
+            }
+        }
+    }
+
+    /// The [`TokenCache`] key, identifying the token endpoint, OAuth2 app and scope,
+    /// and the token provider's identity, if any.
+    fn token_cache_key(&self) -> String {
+        let mut token_cache_key = format!(
+            "{token_url} {app_guid} {scope}",
+            token_url = self.token_url,
+            app_guid = self.app_guid,
+            scope = self.scope
+        );
+
+        if let Some(identity) = self.token_provider.cache_key() {
+            token_cache_key.push(' ');
+            token_cache_key.push_str(identity);
+        }
+
+        token_cache_key
+    }
+
+    /// Send an authenticated request to the Microsoft Graph API and receive the response.
+    ///
+    /// If `client_request_id` is given, it is sent as `client-request-id` header,
+    /// and echoed by the Microsoft Graph API in the response.
+    ///
+    /// The request is built by `build_request`, which may be called repeatedly:
+    ///
+    /// - If the Microsoft Graph API rejects the access token with `401 Unauthorized`,
+    ///   the token is refreshed and the request is retried once.
+    /// - If the Microsoft Graph API throttles the request with `429 Too Many Requests`
+    ///   or `503 Service Unavailable`, the request is retried after the delay suggested by the
+    ///   `Retry-After` response header, within the configured throttling retry budget.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if no valid access token can be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending the request fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code. If the retry budget for throttled requests was exhausted,
+    /// [`OutlookGraphError::retry_after`] holds the delay suggested by the Microsoft Graph API.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if the response body cannot be received.
+    async fn send_graph_request(
+        &self,
+        client_request_id: Option<&str>,
+        build_request: impl Fn(&reqwest::Client) -> reqwest::RequestBuilder,
+    ) -> Result<GraphResponse, OutlookMailerError> {
+        let mut unauthorized_retried = false;
+        let mut throttle_retries = 0;
+
+        loop {
+            let access_token = self.get_access_token().await?;
+
+            let mut request =
+                build_request(&self.http_client).bearer_auth(access_token.expose_secret());
+            if let Some(client_request_id) = client_request_id {
+                request = request
+                    .header(graph::CLIENT_REQUEST_ID, client_request_id)
+                    .header(graph::RETURN_CLIENT_REQUEST_ID, "true");
+            }
+
+            let response = request
+                .send()
+                .await
+                .map_err(OutlookMailerError::SendMailRequest)?;
+
+            let status = response.status();
+
+            // The access token may have been revoked, or expired early.
+            // Refresh it and retry once.
+            if status == StatusCode::UNAUTHORIZED && !unauthorized_retried {
+                #[cfg(feature = "tracing")]
+                info!("Microsoft Graph API rejected the access token; refreshing and retrying...");
+
+                self.invalidate_access_token(&access_token).await;
+                unauthorized_retried = true;
+                continue;
+            }
+
+            if graph::is_throttled(status) && throttle_retries < self.max_throttle_retries {
+                let retry_after = graph::retry_after(response.headers())
+                    .unwrap_or_else(|| graph::fallback_retry_after(throttle_retries));
+
+                if retry_after <= self.max_retry_after {
+                    #[cfg(feature = "tracing")]
+                    info!(
+                        ?retry_after,
+                        "Microsoft Graph API throttled the request with status {status}; retrying..."
+                    );
+
+                    throttle_retries += 1;
+                    tokio::time::sleep(retry_after).await;
+                    continue;
                 }
             }
 
-            success
+            return graph::receive_response(response).await;
         }
-        .map_err(OutlookMailerError::SendMailResponse)?;
+    }
+}
+
+/// Builder for an [`OutlookMailer`], created by [`OutlookMailer::builder`].
+///
+/// All endpoints default to the Microsoft global cloud.
+#[derive(Clone, Debug)]
+pub struct OutlookMailerBuilder {
+    tenant: String,
+    app_guid: String,
+    token_provider: Arc<dyn TokenProvider>,
+    authority_host: String,
+    graph_base_url: String,
+    graph_api_version: String,
+    scopes: Option<Vec<String>>,
+    max_throttle_retries: u32,
+    max_retry_after: Duration,
+    mailbox: OutlookMailbox,
+    save_to_sent_items: bool,
+    token_cache: Arc<dyn TokenCache>,
+    http_client: Option<reqwest::Client>,
+    connect_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    user_agent: Option<String>,
+    root_certificates: Vec<reqwest::Certificate>,
+}
+
+impl OutlookMailerBuilder {
+    /// Create a new builder, using the Microsoft global cloud endpoints.
+    ///
+    /// Pass either a [`SecretString`] client secret or other [`OutlookCredentials`].
+    pub fn new(
+        tenant: String,
+        app_guid: String,
+        credentials: impl Into<OutlookCredentials>,
+    ) -> Self {
+        Self::with_token_provider(tenant, app_guid, credentials.into())
+    }
+
+    /// Create a new builder, using the Microsoft global cloud endpoints
+    /// and retrieving access tokens from a custom [`TokenProvider`].
+    pub fn with_token_provider(
+        tenant: String,
+        app_guid: String,
+        token_provider: impl TokenProvider + 'static,
+    ) -> Self {
+        Self {
+            tenant,
+            app_guid,
+            token_provider: Arc::new(token_provider),
+            authority_host: DEFAULT_AUTHORITY_HOST.into(),
+            graph_base_url: DEFAULT_GRAPH_BASE_URL.into(),
+            graph_api_version: DEFAULT_GRAPH_API_VERSION.into(),
+            scopes: None,
+            max_throttle_retries: DEFAULT_MAX_THROTTLE_RETRIES,
+            max_retry_after: DEFAULT_MAX_RETRY_AFTER,
+            mailbox: OutlookMailbox::Sender,
+            save_to_sent_items: true,
+            token_cache: Arc::new(InMemoryTokenCache::default()),
+            http_client: None,
+            connect_timeout: None,
+            timeout: None,
+            user_agent: None,
+            root_certificates: Vec::new(),
+        }
+    }
+
+    /// Set the Microsoft Identity service authority host, used to request access tokens.
+    ///
+    /// Default: `https://login.microsoftonline.com`
+    ///
+    /// E.g. `https://login.microsoftonline.us` for the US Government cloud,
+    /// or `https://login.chinacloudapi.cn` for the China cloud operated by 21Vianet.
+    pub fn authority_host(mut self, authority_host: impl Into<String>) -> Self {
+        self.authority_host = authority_host.into();
+        self
+    }
+
+    /// Set the Microsoft Graph API base URL.
+    ///
+    /// Default: `https://graph.microsoft.com`
+    ///
+    /// E.g. `https://graph.microsoft.us` for the US Government L4 cloud,
+    /// `https://dod-graph.microsoft.us` for the US Government L5 (DOD) cloud,
+    /// or `https://microsoftgraph.chinacloudapi.cn` for the China cloud operated by 21Vianet.
+    ///
+    /// Unless overridden by [`OutlookMailerBuilder::scopes`],
+    /// the access token scope is derived from the Microsoft Graph API base URL as `{graph_base_url}/.default`.
+    pub fn graph_base_url(mut self, graph_base_url: impl Into<String>) -> Self {
+        self.graph_base_url = graph_base_url.into();
+        self
+    }
+
+    /// Set the Microsoft Graph API version.
+    ///
+    /// Default: `v1.0`
+    pub fn graph_api_version(mut self, graph_api_version: impl Into<String>) -> Self {
+        self.graph_api_version = graph_api_version.into();
+        self
+    }
+
+    /// Set the scopes requested for the access token.
+    ///
+    /// Default: `{graph_base_url}/.default`, e.g. `https://graph.microsoft.com/.default`
+    pub fn scopes(mut self, scopes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.scopes = Some(scopes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Set the number of times a request throttled by the Microsoft Graph API
+    /// with `429 Too Many Requests` or `503 Service Unavailable` is retried.
+    ///
+    /// Default: `3`
+    ///
+    /// Set to `0` to disable retries, returning throttling errors immediately.
+    pub fn max_throttle_retries(mut self, max_throttle_retries: u32) -> Self {
+        self.max_throttle_retries = max_throttle_retries;
+        self
+    }
+
+    /// Set the longest `Retry-After` delay to wait for before retrying a throttled request.
+    ///
+    /// Default: 60 seconds
+    ///
+    /// If the Microsoft Graph API asks for a longer delay, the throttling error is returned immediately,
+    /// holding the suggested delay in [`OutlookGraphError::retry_after`], so callers may back off accordingly.
+    pub fn max_retry_after(mut self, max_retry_after: Duration) -> Self {
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Set the Microsoft Graph API mailbox messages are sent from.
+    ///
+    /// Default: [`OutlookMailbox::Sender`], the mailbox of each message's envelope sender.
+    ///
+    /// Use [`OutlookMailbox::User`] to send from a shared or service mailbox,
+    /// independently of the message's `From:` header.
+    /// Use [`OutlookSendOptions::mailbox`] to override the mailbox per message.
+    pub fn mailbox(mut self, mailbox: OutlookMailbox) -> Self {
+        self.mailbox = mailbox;
+        self
+    }
+
+    /// Set whether sent messages are saved to the Sent Items folder of the sending mailbox.
+    ///
+    /// Default: `true`
+    ///
+    /// The MIME `sendMail` endpoint always saves a copy. If disabled, messages are instead converted
+    /// and sent through the JSON `sendMail` endpoint with `saveToSentItems=false`.
+    /// The conversion is lossy, see [JSON message conversion](crate#json-message-conversion).
+    /// Use [`OutlookSendOptions::save_to_sent_items`] to override the setting per message.
+    pub fn save_to_sent_items(mut self, save_to_sent_items: bool) -> Self {
+        self.save_to_sent_items = save_to_sent_items;
+        self
+    }
+
+    /// Set the [`TokenCache`] consulted before requesting a new access token.
+    ///
+    /// Default: [`InMemoryTokenCache`]
+    ///
+    /// Use a [`FileTokenCache`] to reuse access tokens across process restarts,
+    /// avoiding throttling by the Microsoft Identity service, e.g. for frequently cold-started serverless workers.
+    pub fn token_cache(mut self, token_cache: impl TokenCache + 'static) -> Self {
+        self.token_cache = Arc::new(token_cache);
+        self
+    }
+
+    /// Use a caller-supplied HTTP client for all Microsoft Identity service and Microsoft Graph API requests,
+    /// e.g. to share a connection pool, or to configure a proxy or other settings not covered by this builder.
+    ///
+    /// The [`connect_timeout`](Self::connect_timeout), [`timeout`](Self::timeout),
+    /// [`user_agent`](Self::user_agent) and [`root_certificate`](Self::root_certificate) settings
+    /// only apply to the HTTP client built by the builder, and are ignored if a client is supplied.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Set the timeout for establishing HTTP connections.
+    ///
+    /// Default: no timeout
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Set the total timeout of each HTTP request, from connecting until the response body has been received.
+    ///
+    /// Default: no timeout
+    ///
+    /// Throttling retries and attachment upload chunks are separate requests, each with their own timeout.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Set the `User-Agent` header sent with each HTTP request.
+    ///
+    /// Default: no `User-Agent` header
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. of a corporate TLS-intercepting proxy,
+    /// in addition to the platform's trusted root certificates.
+    ///
+    /// May be called repeatedly to add multiple root certificates.
+    pub fn root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Build the Outlook mailer client, retrieving an initial access token.
+    ///
+    /// Use [`OutlookMailerBuilder::build_lazy`] to defer the access token request until the first message is sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error
+    /// when the attempt to retrieve an access token from the Microsoft Identity Service fails:
+    ///
+    /// - Wrapping an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    /// - Wrapping an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    /// - Wrapping an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    ///   with a non-success HTTP status code.
+    /// - Wrapping an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request.
+    /// - Wrapping an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    /// - Wrapping an [`OutlookAccessTokenError::SignClientAssertion`] error if a client assertion JWT cannot be signed.
+    ///   (Crate feature `certificate` only.)
+    /// - Wrapping an [`OutlookAccessTokenError::ClientAssertionCallback`] error if the client assertion callback fails.
+    /// - Wrapping an [`OutlookAccessTokenError::ReadFederatedTokenFile`] error if a federated token file cannot be read.
+    /// - Wrapping an [`OutlookAccessTokenError::TokenProvider`] error if a custom token provider fails.
+    ///
+    /// Returns an [`OutlookMailerError::BuildHttpClient`] error if the HTTP client cannot be built,
+    /// e.g. because a root certificate cannot be added.
+    ///
+    /// Returns an [`OutlookMailerError::InvalidUrl`] error if the configured endpoints do not form valid URLs.
+    #[cfg_attr(feature = "tracing", instrument)]
+    pub async fn build(self) -> Result<OutlookMailer, OutlookMailerError> {
+        let mailer = self.build_lazy()?;
+
+        // Retrieve the first access token eagerly, failing early on invalid credentials.
+        mailer.warm_up().await?;
+
+        Ok(mailer)
+    }
+
+    /// Build the Outlook mailer client without retrieving an access token.
+    ///
+    /// Only the configuration is validated. The first access token is retrieved
+    /// when the first message is sent, or when calling [`OutlookMailer::warm_up`].
+    /// Use this to construct the mailer in synchronous code,
+    /// or to start up while the Microsoft Identity service is unreachable.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::BuildHttpClient`] error if the HTTP client cannot be built,
+    /// e.g. because a root certificate cannot be added.
+    ///
+    /// Returns an [`OutlookMailerError::InvalidUrl`] error if the configured endpoints do not form valid URLs.
+    #[cfg_attr(feature = "tracing", instrument)]
+    pub fn build_lazy(self) -> Result<OutlookMailer, OutlookMailerError> {
+        let http_client = match self.http_client {
+            Some(http_client) => http_client,
+            None => {
+                let mut http_client = reqwest::Client::builder();
+
+                if let Some(connect_timeout) = self.connect_timeout {
+                    http_client = http_client.connect_timeout(connect_timeout);
+                }
+                if let Some(timeout) = self.timeout {
+                    http_client = http_client.timeout(timeout);
+                }
+                if let Some(user_agent) = self.user_agent {
+                    http_client = http_client.user_agent(user_agent);
+                }
+                if !self.root_certificates.is_empty() {
+                    http_client = http_client.tls_certs_merge(self.root_certificates);
+                }
+
+                http_client
+                    .build()
+                    .map_err(OutlookMailerError::BuildHttpClient)?
+            }
+        };
+
+        let authority_host = self.authority_host.trim_end_matches('/');
+        let graph_base_url = self.graph_base_url.trim_end_matches('/');
+
+        let scope = match self.scopes {
+            Some(scopes) => scopes.join(" "),
+            None => format!("{graph_base_url}/.default"),
+        };
+
+        let token_url = format!(
+            "{authority_host}/{tenant}/oauth2/v2.0/token",
+            tenant = self.tenant
+        );
+        let graph_url = format!(
+            "{graph_base_url}/{graph_api_version}",
+            graph_api_version = self.graph_api_version.trim_matches('/')
+        );
+
+        // Validate the endpoints, failing early on misconfiguration rather than on first use.
+        for url in [&token_url, &graph_url] {
+            reqwest::Url::parse(url).map_err(OutlookMailerError::InvalidUrl)?;
+        }
+
+        Ok(OutlookMailer {
+            http_client,
+            token_url,
+            graph_url,
+            scope,
+            max_throttle_retries: self.max_throttle_retries,
+            max_retry_after: self.max_retry_after,
+            mailbox: self.mailbox,
+            save_to_sent_items: self.save_to_sent_items,
+            token_cache: self.token_cache,
+            app_guid: self.app_guid,
+            token_provider: self.token_provider,
+            access_token: Arc::new(Mutex::new(None)),
+            token_request: Arc::new(Mutex::new(())),
+        })
+    }
+}
+
+// == Mailer ==
+
+#[async_trait]
+impl Mailer for OutlookMailer {
+    type Error = OutlookMailerError;
+
+    /// Send the prepared MIME message via the Microsoft Graph API.
+    /// Statically typed [`Mailer`] implementation for direct
+    /// or generic (`impl Mailer` / `<M: Mailer>`) invocation without vtable dispatch.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookMailerError::RetrieveAccessToken`] error if the cached access token
+    /// needs to be refreshed, but a new access token cannot be retrieved.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailRequest`] error if sending the mailing request to the
+    /// Microsoft Graph API fails.
+    ///
+    /// Returns an [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
+    /// with a non-success HTTP status code.
+    ///
+    /// Returns an [`OutlookMailerError::SendMailResponseBody`] error if the Microsoft Graph API reponse body
+    /// cannot be received.
+    ///
+    /// Returns an [`OutlookMailerError::ParseMessage`] error if a message which cannot be sent through
+    /// the MIME `sendMail` endpoint cannot be parsed for conversion into a JSON message.
+    ///
+    /// Returns an [`OutlookMailerError::ParseResponse`] error if a Microsoft Graph API draft message
+    /// or upload session response cannot be parsed.
+    ///
+    /// Returns an [`OutlookMailerError::MessageTooLarge`] error if a message exceeds the request size limit
+    /// without its attachments.
+    async fn send_mail(&self, message: Message<'_>) -> Result<(), Self::Error> {
+        self.send_mail_with_options(message, &OutlookSendOptions::default())
+            .await?;
 
         Ok(())
     }
@@ -395,23 +1507,13 @@ impl DynMailer for OutlookMailer {
     /// Returns a boxed, type-erased [`OutlookMailerError::SendMailRequest`] error if sending the mailing request to the
     /// Microsoft Graph API fails.
     ///
-    /// Returns a boxed, type-erased  [`OutlookMailerError::SendMailResponse`] error if the Microsoft Graph API responds
+    /// Returns a boxed, type-erased [`OutlookMailerError::Graph`] error if the Microsoft Graph API responds
     /// with a non-success HTTP status code.
     ///
     /// Returns a boxed, type-erased [`OutlookMailerError::SendMailResponseBody`] error if the Microsoft Graph API reponse body
     /// cannot be received.
-    /// (Crate feature `tracing` only: The response body is only received for logging.)
     #[cfg_attr(feature = "tracing", instrument(skip(message)))]
     async fn send_mail(&self, message: Message<'_>) -> Result<(), DynMailerError> {
         Mailer::send_mail(self, message).await.map_err(Into::into)
     }
 }
-
-/// The Microsoft Identity Service access token request JSON success response.
-#[derive(Debug, Deserialize)]
-struct TokenResponse {
-    // token_type: String,
-    // expires_in: i32,
-    // ext_expires_in: i32,
-    access_token: String,
-}
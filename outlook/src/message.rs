@@ -0,0 +1,583 @@
+//! Conversion of MIME messages into Microsoft Graph API JSON message resources.
+//!
+//! The MIME `sendMail` endpoint accepts the raw message as-is,
+//! but is limited in request size and does not support all send options.
+//! The JSON endpoints instead require the message to be split into
+//! its headers, body and attachments.
+//!
+//! See <https://learn.microsoft.com/en-us/graph/api/resources/message?view=graph-rest-1.0>
+
+use async_mailer_core::mail_send::smtp::message::Message;
+use base64::{engine::general_purpose::STANDARD as base64_engine, Engine as _};
+use mail_parser::{MessageParser, MimeHeaders};
+use serde::Serialize;
+
+/// Extended property id of the MAPI property `PidTagDeleteAfterSubmit`.
+///
+/// See <https://learn.microsoft.com/en-us/office/client-developer/outlook/mapi/pidtagdeleteaftersubmit-canonical-property>
+const PID_TAG_DELETE_AFTER_SUBMIT: &str = "Boolean 0x0E01";
+
+/// Extended property id of the MAPI property `PidTagInReplyToId`, holding the `In-Reply-To` header.
+///
+/// See <https://learn.microsoft.com/en-us/office/client-developer/outlook/mapi/pidtaginreplytoid-canonical-property>
+const PID_TAG_IN_REPLY_TO_ID: &str = "String 0x1042";
+
+/// Extended property id of the MAPI property `PidTagInternetReferences`, holding the `References` header.
+///
+/// See <https://learn.microsoft.com/en-us/office/client-developer/outlook/mapi/pidtaginternetreferences-canonical-property>
+const PID_TAG_INTERNET_REFERENCES: &str = "String 0x1039";
+
+/// Extract the `Message-ID` header of the MIME message, if present.
+pub(crate) fn internet_message_id(message: &Message<'_>) -> Option<String> {
+    MessageParser::default()
+        .parse_headers(message.body.as_ref())?
+        .message_id()
+        .map(ToString::to_string)
+}
+
+/// A MIME message, converted into a Microsoft Graph API JSON message resource and its attachments.
+#[derive(Debug)]
+pub(crate) struct GraphMessage {
+    /// The message resource, without attachments.
+    pub(crate) message: MessageResource,
+
+    /// The message attachments, to be added inline or uploaded separately.
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+impl GraphMessage {
+    /// Parse the MIME message body and convert it into a Microsoft Graph API JSON message resource.
+    ///
+    /// Envelope recipients not listed in the `To`, `Cc` or `Bcc` headers are added as `Bcc` recipients.
+    ///
+    /// The conversion is lossy, as documented in the [crate documentation](crate#json-message-conversion):
+    /// only one body is retained, preferring HTML over plain text,
+    /// and only the `Message-ID`, `In-Reply-To`, `References` and custom `X-` headers are retained.
+    ///
+    /// Returns `None` if the MIME message cannot be parsed.
+    pub(crate) fn parse(message: &Message<'_>) -> Option<Self> {
+        let parsed = MessageParser::default().parse(message.body.as_ref())?;
+
+        let recipients = |address: Option<&mail_parser::Address<'_>>| {
+            address
+                .into_iter()
+                .flat_map(|address| address.iter())
+                .filter_map(Recipient::from_addr)
+                .collect::<Vec<_>>()
+        };
+
+        let to_recipients = recipients(parsed.to());
+        let cc_recipients = recipients(parsed.cc());
+        let mut bcc_recipients = recipients(parsed.bcc());
+
+        for envelope_recipient in &message.rcpt_to {
+            let address = envelope_recipient.email.as_ref();
+            let is_listed = to_recipients
+                .iter()
+                .chain(&cc_recipients)
+                .chain(&bcc_recipients)
+                .any(|recipient| {
+                    recipient
+                        .email_address
+                        .address
+                        .eq_ignore_ascii_case(address)
+                });
+
+            if !is_listed {
+                bcc_recipients.push(Recipient::new(None, address.to_string()));
+            }
+        }
+
+        let has_html_body = parsed.html_bodies().any(|part| part.is_text_html());
+        let body = if has_html_body {
+            ItemBody {
+                content_type: "HTML",
+                content: parsed.body_html(0).unwrap_or_default().into_owned(),
+            }
+        } else {
+            ItemBody {
+                content_type: "Text",
+                content: parsed.body_text(0).unwrap_or_default().into_owned(),
+            }
+        };
+
+        // Microsoft Graph API only accepts custom `X-` headers.
+        // Threading headers are instead set through their MAPI properties.
+        let internet_message_headers = parsed
+            .headers_raw()
+            .filter(|(name, _)| name.len() > 2 && name[..2].eq_ignore_ascii_case("x-"))
+            .map(|(name, value)| InternetMessageHeader {
+                name: name.to_string(),
+                value: value.trim().to_string(),
+            })
+            .collect();
+
+        let single_value_extended_properties = [
+            (PID_TAG_IN_REPLY_TO_ID, "In-Reply-To"),
+            (PID_TAG_INTERNET_REFERENCES, "References"),
+        ]
+        .into_iter()
+        .filter_map(|(id, header)| {
+            Some(SingleValueExtendedProperty {
+                id,
+                // Unfold the header value.
+                value: parsed
+                    .header_raw(header)?
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            })
+        })
+        .filter(|property| !property.value.is_empty())
+        .collect();
+
+        let attachments = parsed
+            .attachments()
+            .map(|part| {
+                let content_type = part
+                    .content_type()
+                    .map(|content_type| {
+                        format!(
+                            "{}/{}",
+                            content_type.ctype(),
+                            content_type.subtype().unwrap_or("octet-stream")
+                        )
+                    })
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+
+                Attachment {
+                    name: part.attachment_name().unwrap_or("attachment").to_string(),
+                    content_type,
+                    content_id: part.content_id().map(ToString::to_string),
+                    is_inline: part
+                        .content_disposition()
+                        .is_some_and(|disposition| disposition.is_inline()),
+                    contents: part.contents().to_vec(),
+                }
+            })
+            .collect();
+
+        Some(Self {
+            message: MessageResource {
+                subject: parsed.subject().map(ToString::to_string),
+                // Retain the `Message-ID`, rather than letting Exchange Online generate a new one.
+                internet_message_id: parsed.message_id().map(|id| format!("<{id}>")),
+                body,
+                from: recipients(parsed.from()).into_iter().next(),
+                to_recipients,
+                cc_recipients,
+                bcc_recipients,
+                reply_to: recipients(parsed.reply_to()),
+                internet_message_headers,
+                single_value_extended_properties,
+            },
+            attachments,
+        })
+    }
+}
+
+impl GraphMessage {
+    /// Mark the message to be deleted after it was sent, rather than saved to the Sent Items folder.
+    ///
+    /// Sets the MAPI property `PidTagDeleteAfterSubmit`, which is the draft message equivalent
+    /// of the `saveToSentItems` parameter of the JSON `sendMail` endpoint.
+    pub(crate) fn delete_after_submit(&mut self) {
+        self.message
+            .single_value_extended_properties
+            .push(SingleValueExtendedProperty {
+                id: PID_TAG_DELETE_AFTER_SUBMIT,
+                value: "true".to_string(),
+            });
+    }
+
+    /// Build a JSON `sendMail` request, with all attachments inlined as base64-encoded file attachments.
+    ///
+    /// See <https://learn.microsoft.com/en-us/graph/api/user-sendmail?view=graph-rest-1.0>
+    pub(crate) fn to_send_mail_request(&self, save_to_sent_items: bool) -> SendMailRequest<'_> {
+        SendMailRequest {
+            message: MessageWithAttachments {
+                message: &self.message,
+                attachments: self
+                    .attachments
+                    .iter()
+                    .map(Attachment::to_file_attachment)
+                    .collect(),
+            },
+            save_to_sent_items,
+        }
+    }
+
+    /// Serialize a JSON `sendMail` request body, with all attachments inlined as base64-encoded file attachments.
+    pub(crate) fn to_send_mail_json(&self, save_to_sent_items: bool) -> Vec<u8> {
+        // Serializing plain structs of strings into a `Vec` cannot fail.
+        serde_json::to_vec(&self.to_send_mail_request(save_to_sent_items)).unwrap_or_default()
+    }
+
+    /// Serialize a JSON message resource request body, without attachments, e.g. to create a draft message.
+    pub(crate) fn to_message_json(&self) -> Vec<u8> {
+        // Serializing plain structs of strings into a `Vec` cannot fail.
+        serde_json::to_vec(&self.message).unwrap_or_default()
+    }
+}
+
+/// Microsoft Graph API JSON `sendMail` request.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SendMailRequest<'a> {
+    message: MessageWithAttachments<'a>,
+    save_to_sent_items: bool,
+}
+
+/// Microsoft Graph API JSON message resource with inline file attachments.
+#[derive(Serialize)]
+struct MessageWithAttachments<'a> {
+    #[serde(flatten)]
+    message: &'a MessageResource,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<FileAttachment>,
+}
+
+/// Microsoft Graph API JSON message resource.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct MessageResource {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    internet_message_id: Option<String>,
+    body: ItemBody,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from: Option<Recipient>,
+    to_recipients: Vec<Recipient>,
+    cc_recipients: Vec<Recipient>,
+    bcc_recipients: Vec<Recipient>,
+    reply_to: Vec<Recipient>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    internet_message_headers: Vec<InternetMessageHeader>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    single_value_extended_properties: Vec<SingleValueExtendedProperty>,
+}
+
+/// Microsoft Graph API JSON item body.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ItemBody {
+    content_type: &'static str,
+    content: String,
+}
+
+/// Microsoft Graph API JSON recipient.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Recipient {
+    email_address: EmailAddress,
+}
+
+impl Recipient {
+    fn new(name: Option<String>, address: String) -> Self {
+        Self {
+            email_address: EmailAddress { name, address },
+        }
+    }
+
+    fn from_addr(addr: &mail_parser::Addr<'_>) -> Option<Self> {
+        Some(Self::new(
+            addr.name().map(ToString::to_string),
+            addr.address()?.to_string(),
+        ))
+    }
+}
+
+/// Microsoft Graph API JSON email address.
+#[derive(Debug, Serialize)]
+struct EmailAddress {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    address: String,
+}
+
+/// Microsoft Graph API JSON single-value extended property.
+#[derive(Debug, Serialize)]
+struct SingleValueExtendedProperty {
+    id: &'static str,
+    value: String,
+}
+
+/// Microsoft Graph API JSON internet message header.
+#[derive(Debug, Serialize)]
+struct InternetMessageHeader {
+    name: String,
+    value: String,
+}
+
+/// A message attachment, extracted from the MIME message.
+#[derive(Debug)]
+pub(crate) struct Attachment {
+    pub(crate) name: String,
+    pub(crate) content_type: String,
+    pub(crate) content_id: Option<String>,
+    pub(crate) is_inline: bool,
+    pub(crate) contents: Vec<u8>,
+}
+
+impl Attachment {
+    /// Convert into a Microsoft Graph API JSON file attachment with base64-encoded contents.
+    pub(crate) fn to_file_attachment(&self) -> FileAttachment {
+        FileAttachment {
+            odata_type: "#microsoft.graph.fileAttachment",
+            name: self.name.clone(),
+            content_type: self.content_type.clone(),
+            content_id: self.content_id.clone(),
+            is_inline: self.is_inline,
+            content_bytes: base64_engine.encode(&self.contents),
+        }
+    }
+
+    /// Convert into a Microsoft Graph API JSON attachment item, describing an attachment upload session.
+    pub(crate) fn to_attachment_item(&self) -> AttachmentItem {
+        AttachmentItem {
+            attachment_type: "file",
+            name: self.name.clone(),
+            size: self.contents.len(),
+            content_type: self.content_type.clone(),
+            content_id: self.content_id.clone(),
+            is_inline: self.is_inline,
+        }
+    }
+}
+
+/// Microsoft Graph API JSON file attachment.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FileAttachment {
+    #[serde(rename = "@odata.type")]
+    odata_type: &'static str,
+    name: String,
+    content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+    is_inline: bool,
+    content_bytes: String,
+}
+
+/// Microsoft Graph API JSON attachment item.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AttachmentItem {
+    attachment_type: &'static str,
+    name: String,
+    size: usize,
+    content_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+    is_inline: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use async_mailer_core::mail_send::mail_builder::{self, MessageBuilder};
+    use async_mailer_core::mail_send::smtp::message::IntoMessage;
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn builder() -> MessageBuilder<'static> {
+        MessageBuilder::new()
+            .from(("From", "from@example.com"))
+            .to("to@example.com")
+            .subject("Subject")
+    }
+
+    /// Convert the message and serialize its JSON message resource.
+    fn message_json(message: &Message<'_>) -> Value {
+        let graph_message = GraphMessage::parse(message).unwrap();
+
+        serde_json::to_value(&graph_message.message).unwrap()
+    }
+
+    #[test]
+    fn retains_message_id_as_internet_message_id() {
+        let message = builder()
+            .message_id("unique@example.com")
+            .text_body("Body")
+            .into_message()
+            .unwrap();
+
+        assert_eq!(
+            message_json(&message)["internetMessageId"],
+            json!("<unique@example.com>")
+        );
+        assert_eq!(
+            internet_message_id(&message).as_deref(),
+            Some("unique@example.com")
+        );
+    }
+
+    #[test]
+    fn converts_recipients() {
+        let mut message = MessageBuilder::new()
+            .from(("From", "from@example.com"))
+            .to(vec![("To", "to@example.com")])
+            .cc("cc@example.com")
+            .bcc("bcc@example.com")
+            .reply_to("reply@example.com")
+            .subject("Subject")
+            .text_body("Body")
+            .into_message()
+            .unwrap();
+        message.rcpt_to.push("envelope@example.com".into());
+
+        let json = message_json(&message);
+
+        assert_eq!(
+            json["from"],
+            json!({ "emailAddress": { "name": "From", "address": "from@example.com" } })
+        );
+        assert_eq!(
+            json["toRecipients"],
+            json!([{ "emailAddress": { "name": "To", "address": "to@example.com" } }])
+        );
+        assert_eq!(
+            json["ccRecipients"],
+            json!([{ "emailAddress": { "address": "cc@example.com" } }])
+        );
+        // Envelope recipients not listed in the headers are added as `Bcc` recipients.
+        assert_eq!(
+            json["bccRecipients"],
+            json!([
+                { "emailAddress": { "address": "bcc@example.com" } },
+                { "emailAddress": { "address": "envelope@example.com" } },
+            ])
+        );
+        assert_eq!(
+            json["replyTo"],
+            json!([{ "emailAddress": { "address": "reply@example.com" } }])
+        );
+        assert_eq!(json["subject"], json!("Subject"));
+    }
+
+    #[test]
+    fn converts_text_body() {
+        let message = builder().text_body("Plain text").into_message().unwrap();
+
+        assert_eq!(
+            message_json(&message)["body"],
+            json!({ "contentType": "Text", "content": "Plain text" })
+        );
+    }
+
+    #[test]
+    fn prefers_html_body_over_text_alternative() {
+        let message = builder()
+            .text_body("Plain text")
+            .html_body("<p>HTML</p>")
+            .into_message()
+            .unwrap();
+
+        assert_eq!(
+            message_json(&message)["body"],
+            json!({ "contentType": "HTML", "content": "<p>HTML</p>" })
+        );
+    }
+
+    #[test]
+    fn retains_custom_and_threading_headers() {
+        let message = builder()
+            .in_reply_to("parent@example.com")
+            .references(vec!["root@example.com", "parent@example.com"])
+            .header("X-Custom", mail_builder::headers::raw::Raw::new("value"))
+            .header(
+                "List-Unsubscribe",
+                mail_builder::headers::raw::Raw::new("<mailto:unsubscribe@example.com>"),
+            )
+            .text_body("Body")
+            .into_message()
+            .unwrap();
+
+        let json = message_json(&message);
+
+        assert_eq!(
+            json["internetMessageHeaders"],
+            json!([{ "name": "X-Custom", "value": "value" }])
+        );
+        assert_eq!(
+            json["singleValueExtendedProperties"],
+            json!([
+                { "id": PID_TAG_IN_REPLY_TO_ID, "value": "<parent@example.com>" },
+                {
+                    "id": PID_TAG_INTERNET_REFERENCES,
+                    "value": "<root@example.com> <parent@example.com>",
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn omits_absent_optional_fields() {
+        let message = builder().text_body("Body").into_message().unwrap();
+
+        let json = message_json(&message);
+
+        assert!(json.get("internetMessageHeaders").is_none());
+        assert!(json.get("singleValueExtendedProperties").is_none());
+    }
+
+    #[test]
+    fn extracts_attachments() {
+        let message = builder()
+            .text_body("Body")
+            .attachment("application/pdf", "report.pdf", b"%PDF".as_slice())
+            .inline("image/png", "logo", b"PNG".as_slice())
+            .into_message()
+            .unwrap();
+
+        let graph_message = GraphMessage::parse(&message).unwrap();
+
+        let attachments: Vec<_> = graph_message
+            .attachments
+            .iter()
+            .map(|attachment| {
+                (
+                    attachment.name.as_str(),
+                    attachment.content_type.as_str(),
+                    attachment.content_id.as_deref(),
+                    attachment.is_inline,
+                    attachment.contents.as_slice(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            attachments,
+            [
+                (
+                    "report.pdf",
+                    "application/pdf",
+                    None,
+                    false,
+                    b"%PDF".as_slice()
+                ),
+                (
+                    "attachment",
+                    "image/png",
+                    Some("logo"),
+                    true,
+                    b"PNG".as_slice()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_message_to_be_deleted_after_submit() {
+        let message = builder().text_body("Body").into_message().unwrap();
+        let mut graph_message = GraphMessage::parse(&message).unwrap();
+
+        graph_message.delete_after_submit();
+
+        assert_eq!(
+            serde_json::to_value(&graph_message.message).unwrap()["singleValueExtendedProperties"],
+            json!([{ "id": PID_TAG_DELETE_AFTER_SUBMIT, "value": "true" }])
+        );
+    }
+}
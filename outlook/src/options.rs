@@ -0,0 +1,84 @@
+//! Mailbox selection and per-message send options of the [`OutlookMailer`](crate::OutlookMailer).
+
+use async_mailer_core::mail_send::smtp::message::Message;
+
+/// The Microsoft Graph API mailbox a message is sent from.
+///
+/// The mailbox is independent of the MIME `From:` header.
+/// Sending from a shared or service mailbox while the `From:` header shows a different address
+/// requires the `Send As` permission on that address.
+/// With the `Send on Behalf` permission instead,
+/// the message is delivered with the mailbox as `Sender:` on behalf of the `From:` address.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutlookMailbox {
+    /// Send from the mailbox of the envelope sender, i.e. the message's `mail_from` address.
+    #[default]
+    Sender,
+
+    /// Send from the mailbox of the given user, identified by its object id or user principal name (UPN).
+    User(String),
+
+    /// Send from the mailbox of the signed-in user (`/me`).
+    /// Only valid with delegated access tokens.
+    Me,
+}
+
+impl OutlookMailbox {
+    /// Send from the mailbox of the given user, identified by its object id or user principal name (UPN).
+    pub fn user(user: impl Into<String>) -> Self {
+        Self::User(user.into())
+    }
+
+    /// The Microsoft Graph API resource path of the mailbox, relative to the API version root.
+    pub(crate) fn path(&self, message: &Message<'_>) -> String {
+        match self {
+            Self::Sender => format!("users/{}", message.mail_from.email),
+            Self::User(user) => format!("users/{user}"),
+            Self::Me => "me".to_string(),
+        }
+    }
+}
+
+/// Per-message options, passed to [`OutlookMailer::send_mail_with_options`](crate::OutlookMailer::send_mail_with_options).
+///
+/// Unset options fall back to the mailer's configuration.
+#[derive(Clone, Debug, Default)]
+pub struct OutlookSendOptions {
+    pub(crate) mailbox: Option<OutlookMailbox>,
+    pub(crate) save_to_sent_items: Option<bool>,
+    pub(crate) client_request_id: Option<String>,
+}
+
+impl OutlookSendOptions {
+    /// Create new send options, using the mailer's configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send from the given mailbox, overriding [`OutlookMailerBuilder::mailbox`](crate::OutlookMailerBuilder::mailbox).
+    pub fn mailbox(mut self, mailbox: OutlookMailbox) -> Self {
+        self.mailbox = Some(mailbox);
+        self
+    }
+
+    /// Set whether the message is saved to the Sent Items folder,
+    /// overriding [`OutlookMailerBuilder::save_to_sent_items`](crate::OutlookMailerBuilder::save_to_sent_items).
+    ///
+    /// Messages not saved to Sent Items are converted into JSON messages,
+    /// see [JSON message conversion](crate#json-message-conversion).
+    pub fn save_to_sent_items(mut self, save_to_sent_items: bool) -> Self {
+        self.save_to_sent_items = Some(save_to_sent_items);
+        self
+    }
+
+    /// Set the Microsoft Graph API `client-request-id` sent with all requests sending the message,
+    /// e.g. to correlate the message with a request id of the calling application.
+    ///
+    /// Default: a random UUID, generated per message.
+    ///
+    /// The client request id is returned in [`OutlookSendReceipt::client_request_id`](crate::OutlookSendReceipt::client_request_id).
+    pub fn client_request_id(mut self, client_request_id: impl Into<String>) -> Self {
+        self.client_request_id = Some(client_request_id.into());
+        self
+    }
+}
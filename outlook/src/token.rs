@@ -0,0 +1,474 @@
+//! Access token acquisition for the [`OutlookMailer`](crate::OutlookMailer).
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use secrecy::SecretString;
+use serde::Deserialize;
+
+#[cfg(feature = "tracing")]
+use tracing::instrument;
+
+use crate::credentials::ClientAuthentication;
+use crate::graph;
+use crate::{OutlookAccessTokenError, OutlookCredentials};
+
+/// Refresh the cached access token if it expires within this margin,
+/// or within half of its lifetime, if shorter.
+const ACCESS_TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(5 * 60);
+
+/// Environment variable pointing to the federated token file, as set up by Azure workload identity.
+const AZURE_FEDERATED_TOKEN_FILE: &str = "AZURE_FEDERATED_TOKEN_FILE";
+
+/// A Microsoft Graph API access token, as returned by a [`TokenProvider`].
+#[derive(Clone, Debug)]
+pub struct OutlookAccessToken {
+    secret: SecretString,
+    issued_at: Instant,
+    expires_at: Instant,
+}
+
+impl OutlookAccessToken {
+    /// Create a new access token, expiring at `expires_at`.
+    ///
+    /// The token's lifetime is measured from now.
+    pub fn new(secret: SecretString, expires_at: Instant) -> Self {
+        Self::with_issued_at(secret, Instant::now(), expires_at)
+    }
+
+    /// Create a new access token, issued at `issued_at` and expiring at `expires_at`,
+    /// e.g. when restoring a token from a [`TokenCache`](crate::TokenCache).
+    pub fn with_issued_at(secret: SecretString, issued_at: Instant, expires_at: Instant) -> Self {
+        Self {
+            secret,
+            issued_at,
+            expires_at,
+        }
+    }
+
+    /// The bearer token secret.
+    pub fn secret(&self) -> &SecretString {
+        &self.secret
+    }
+
+    /// The instant at which the access token was issued, from which its lifetime is measured.
+    pub fn issued_at(&self) -> Instant {
+        self.issued_at
+    }
+
+    /// The instant at which the access token expires.
+    pub fn expires_at(&self) -> Instant {
+        self.expires_at
+    }
+
+    /// Whether the token has expired or is about to expire, and should be refreshed.
+    ///
+    /// The refresh margin is clamped to half of the token's lifetime,
+    /// so short-lived tokens are not considered expired as soon as they are issued.
+    pub(crate) fn needs_refresh(&self) -> bool {
+        let lifetime = self.expires_at.saturating_duration_since(self.issued_at);
+        let refresh_margin = ACCESS_TOKEN_REFRESH_MARGIN.min(lifetime / 2);
+
+        Instant::now() + refresh_margin >= self.expires_at
+    }
+}
+
+/// Parameters of an access token request, passed to [`TokenProvider::access_token`]
+/// by the [`OutlookMailer`](crate::OutlookMailer).
+#[derive(Clone, Copy, Debug)]
+pub struct TokenRequest<'a> {
+    pub(crate) http_client: &'a reqwest::Client,
+    pub(crate) token_url: &'a str,
+    pub(crate) client_id: &'a str,
+    pub(crate) scope: &'a str,
+}
+
+impl<'a> TokenRequest<'a> {
+    /// The mailer's HTTP client, to be used for token requests.
+    pub fn http_client(&self) -> &'a reqwest::Client {
+        self.http_client
+    }
+
+    /// The Microsoft Identity service OAuth2 token endpoint URL.
+    pub fn token_url(&self) -> &'a str {
+        self.token_url
+    }
+
+    /// The OAuth2 app (client) GUID.
+    pub fn client_id(&self) -> &'a str {
+        self.client_id
+    }
+
+    /// The space-separated scopes to request.
+    pub fn scope(&self) -> &'a str {
+        self.scope
+    }
+
+    /// Request an access token from the Microsoft Identity service, using the OAuth2 client credentials grant.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::OAuth2`] error if the Microsoft Identity service rejects the request,
+    /// e.g. because of invalid credentials.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    pub(crate) async fn client_credentials_grant(
+        &self,
+        client_authentication: ClientAuthentication,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError> {
+        let requested_at = Instant::now();
+
+        let mut form_data = vec![
+            ("client_id", self.client_id),
+            ("grant_type", "client_credentials"),
+            ("scope", self.scope),
+        ];
+        form_data.extend(client_authentication.form_data());
+
+        let token_response: TokenResponse =
+            match post_token_form(self, self.token_url, &form_data).await? {
+                TokenEndpointResponse::Success(response) => response,
+                TokenEndpointResponse::Error(error) => return Err(error.into()),
+            };
+
+        Ok(OutlookAccessToken::with_issued_at(
+            SecretString::from(token_response.access_token),
+            requested_at,
+            // Measure validity from the time of the request, erring on the side of an early refresh.
+            requested_at + Duration::from_secs(token_response.expires_in),
+        ))
+    }
+}
+
+/// Source of Microsoft Graph API access tokens for the [`OutlookMailer`](crate::OutlookMailer).
+///
+/// The mailer caches the returned [`OutlookAccessToken`] and calls the provider again
+/// shortly before the token expires, or after the Microsoft Graph API rejected it.
+///
+/// Implemented by:
+///
+/// - [`OutlookCredentials`], using the OAuth2 client credentials grant
+///   with a client secret, a certificate or a client assertion callback.
+/// - [`FederatedTokenFile`], using the OAuth2 client credentials grant
+///   with a federated token file, e.g. for Kubernetes workload identity.
+/// - [`FnTokenProvider`], wrapping an arbitrary async closure.
+/// - [`DelegatedTokenProvider`](crate::DelegatedTokenProvider), using the OAuth2 refresh token
+///   and device code grants to send as the signed-in user.
+#[async_trait]
+pub trait TokenProvider: std::fmt::Debug + Send + Sync {
+    /// Retrieve a new access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError`] if the access token cannot be retrieved.
+    async fn access_token(
+        &self,
+        request: &TokenRequest<'_>,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError>;
+
+    /// The identity on whose behalf access tokens are issued, e.g. a signed-in user,
+    /// distinguishing this provider's tokens from those of other providers in a shared
+    /// [`TokenCache`](crate::TokenCache).
+    ///
+    /// Default: `None`, for app-only access tokens, identified by the token endpoint, OAuth2 app and scope alone.
+    fn cache_key(&self) -> Option<&str> {
+        None
+    }
+}
+
+#[async_trait]
+impl TokenProvider for OutlookCredentials {
+    /// Retrieve an OAuth2 client credentials grant access token from the Microsoft Identity service.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    ///
+    /// Returns an [`OutlookAccessTokenError::SignClientAssertion`] error if a certificate-signed client assertion JWT cannot be signed.
+    /// (Crate feature `certificate` only.)
+    ///
+    /// Returns an [`OutlookAccessTokenError::ClientAssertionCallback`] error if the client assertion callback fails.
+    #[cfg_attr(feature = "tracing", instrument(skip(request)))]
+    async fn access_token(
+        &self,
+        request: &TokenRequest<'_>,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError> {
+        let client_authentication = self
+            .client_authentication(request.client_id, request.token_url)
+            .await?;
+
+        request
+            .client_credentials_grant(client_authentication)
+            .await
+    }
+}
+
+/// Federated token file [`TokenProvider`], e.g. for Kubernetes workload identity.
+///
+/// The federated token, such as a projected Kubernetes service account token,
+/// is exchanged for an access token using the OAuth2 client credentials grant with a client assertion.
+///
+/// The file is re-read on each token refresh, as its contents are rotated by the platform.
+#[derive(Clone, Debug)]
+pub struct FederatedTokenFile {
+    path: PathBuf,
+}
+
+impl FederatedTokenFile {
+    /// Create a new federated token file provider, reading the token from `path`.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// Create a new federated token file provider,
+    /// reading the token from the path set in the `AZURE_FEDERATED_TOKEN_FILE` environment variable.
+    ///
+    /// Returns `None` if the environment variable is not set.
+    pub fn from_env() -> Option<Self> {
+        std::env::var_os(AZURE_FEDERATED_TOKEN_FILE).map(Self::new)
+    }
+}
+
+#[async_trait]
+impl TokenProvider for FederatedTokenFile {
+    /// Read the federated token file and exchange its contents
+    /// for an OAuth2 client credentials grant access token from the Microsoft Identity service.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReadFederatedTokenFile`] error if the federated token file cannot be read.
+    ///
+    /// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the token request fails.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+    ///
+    /// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+    /// with a non-success HTTP status code, e.g. because it throttles the request.
+    ///
+    /// Returns an [`OutlookAccessTokenError::ParseResponse`] error if the response body bytes cannot be parsed as JSON.
+    #[cfg_attr(feature = "tracing", instrument(skip(request)))]
+    async fn access_token(
+        &self,
+        request: &TokenRequest<'_>,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError> {
+        let assertion = tokio::fs::read_to_string(&self.path)
+            .await
+            .map_err(OutlookAccessTokenError::ReadFederatedTokenFile)?;
+
+        request
+            .client_credentials_grant(ClientAuthentication::Assertion(SecretString::from(
+                assertion.trim(),
+            )))
+            .await
+    }
+}
+
+/// Type-erased error returned by the closure of an [`FnTokenProvider`].
+pub type TokenProviderError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// [`TokenProvider`] wrapping an arbitrary async closure.
+///
+/// The closure is called whenever a new access token is required.
+/// It is responsible for acquiring the token by any means, e.g. from a sidecar or a managed identity endpoint.
+///
+/// If the closure's tokens are not app-only tokens of the mailer's OAuth2 app,
+/// set a [`FnTokenProvider::cache_key`] before sharing a [`TokenCache`](crate::TokenCache) with other mailers.
+#[derive(Clone)]
+pub struct FnTokenProvider<F> {
+    closure: F,
+    cache_key: Option<String>,
+}
+
+impl<F, Fut, E> FnTokenProvider<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<OutlookAccessToken, E>> + Send,
+    E: Into<TokenProviderError>,
+{
+    /// Create a new token provider from an async closure returning an [`OutlookAccessToken`].
+    pub fn new(closure: F) -> Self {
+        Self {
+            closure,
+            cache_key: None,
+        }
+    }
+
+    /// Set the identity on whose behalf the closure's access tokens are issued,
+    /// distinguishing them from the tokens of other providers in a shared [`TokenCache`](crate::TokenCache).
+    ///
+    /// See [`TokenProvider::cache_key`].
+    pub fn cache_key(mut self, cache_key: impl Into<String>) -> Self {
+        self.cache_key = Some(cache_key.into());
+        self
+    }
+}
+
+impl<F> std::fmt::Debug for FnTokenProvider<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnTokenProvider")
+            .field("cache_key", &self.cache_key)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<F, Fut, E> TokenProvider for FnTokenProvider<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<OutlookAccessToken, E>> + Send,
+    E: Into<TokenProviderError>,
+{
+    /// Call the closure to retrieve a new access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`OutlookAccessTokenError::TokenProvider`] error if the closure fails.
+    async fn access_token(
+        &self,
+        _request: &TokenRequest<'_>,
+    ) -> Result<OutlookAccessToken, OutlookAccessTokenError> {
+        (self.closure)()
+            .await
+            .map_err(|error| OutlookAccessTokenError::TokenProvider(error.into()))
+    }
+
+    fn cache_key(&self) -> Option<&str> {
+        self.cache_key.as_deref()
+    }
+}
+
+/// Post a form to a Microsoft Identity service OAuth2 endpoint, and parse the success or error response.
+///
+/// # Errors
+///
+/// Returns an [`OutlookAccessTokenError::SendRequest`] error if sending the request fails.
+///
+/// Returns an [`OutlookAccessTokenError::ReceiveResponse`] error if the response body cannot be received.
+///
+/// Returns an [`OutlookAccessTokenError::HttpStatus`] error if the Microsoft Identity service responds
+/// with `408 Request Timeout`, `429 Too Many Requests` or a `5xx` server error,
+/// or with any other non-success HTTP status code and no OAuth2 error response body.
+///
+/// Returns an [`OutlookAccessTokenError::ParseResponse`] error if a success response body cannot be parsed as JSON.
+pub(crate) async fn post_token_form<T: serde::de::DeserializeOwned>(
+    request: &TokenRequest<'_>,
+    url: &str,
+    form_data: &[(&str, &str)],
+) -> Result<TokenEndpointResponse<T>, OutlookAccessTokenError> {
+    let response = request
+        .http_client()
+        .post(url)
+        .form(form_data)
+        .send()
+        .await
+        .map_err(OutlookAccessTokenError::SendRequest)?;
+
+    let status = response.status();
+
+    let response_data = response
+        .bytes()
+        .await
+        .map_err(OutlookAccessTokenError::ReceiveResponse)?;
+
+    if status.is_success() {
+        return serde_json::from_slice(&response_data)
+            .map(TokenEndpointResponse::Success)
+            .map_err(OutlookAccessTokenError::ParseResponse);
+    }
+
+    // Throttling and server errors may come with an OAuth2 error response body,
+    // but must still be reported as transient.
+    if graph::is_transient_status(status) {
+        return Err(OutlookAccessTokenError::HttpStatus(status));
+    }
+
+    serde_json::from_slice(&response_data)
+        .map(TokenEndpointResponse::Error)
+        .map_err(|_| OutlookAccessTokenError::HttpStatus(status))
+}
+
+/// Microsoft Identity service OAuth2 endpoint JSON response.
+#[derive(Debug)]
+pub(crate) enum TokenEndpointResponse<T> {
+    Success(T),
+    Error(TokenErrorResponse),
+}
+
+/// Microsoft Identity service OAuth2 JSON error response.
+#[derive(Debug, Deserialize)]
+pub(crate) struct TokenErrorResponse {
+    pub(crate) error: String,
+    error_description: Option<String>,
+}
+
+impl From<TokenErrorResponse> for OutlookAccessTokenError {
+    fn from(response: TokenErrorResponse) -> Self {
+        Self::OAuth2(match response.error_description {
+            Some(description) => format!("{}: {description}", response.error),
+            None => response.error,
+        })
+    }
+}
+
+/// The Microsoft Identity Service access token request JSON success response.
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    // token_type: String,
+    expires_in: u64,
+    // ext_expires_in: i32,
+    access_token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(issued_at: Instant, lifetime: Duration) -> OutlookAccessToken {
+        OutlookAccessToken {
+            secret: SecretString::from("token"),
+            issued_at,
+            expires_at: issued_at + lifetime,
+        }
+    }
+
+    #[test]
+    fn fresh_token_does_not_need_refresh() {
+        assert!(!token(Instant::now(), Duration::from_secs(3600)).needs_refresh());
+    }
+
+    #[test]
+    fn token_expiring_within_margin_needs_refresh() {
+        let issued_at = Instant::now() - Duration::from_secs(3600 - 4 * 60);
+
+        assert!(token(issued_at, Duration::from_secs(3600)).needs_refresh());
+    }
+
+    #[test]
+    fn short_lived_token_does_not_need_refresh_when_issued() {
+        assert!(!token(Instant::now(), Duration::from_secs(60)).needs_refresh());
+        assert!(!token(Instant::now(), Duration::from_secs(5 * 60)).needs_refresh());
+    }
+
+    #[test]
+    fn short_lived_token_needs_refresh_after_half_its_lifetime() {
+        let issued_at = Instant::now() - Duration::from_secs(31);
+
+        assert!(token(issued_at, Duration::from_secs(60)).needs_refresh());
+    }
+}
@@ -0,0 +1,299 @@
+//! Access token refresh of the `OutlookMailer`, tested against a stub Microsoft Identity service
+//! and Microsoft Graph API server.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_mailer_core::mail_send::mail_builder::MessageBuilder;
+use async_mailer_core::mail_send::smtp::message::{IntoMessage, Message};
+use async_mailer_outlook::{OutlookAccessTokenError, OutlookMailer, OutlookMailerError};
+use secrecy::SecretString;
+
+mod common;
+
+/// Stub server, answering token requests with sequentially numbered access tokens,
+/// and recording the bearer tokens of all other (Microsoft Graph API) requests.
+struct StubServer {
+    url: String,
+    token_requests: Arc<AtomicUsize>,
+    graph_authorizations: Arc<Mutex<Vec<String>>>,
+}
+
+/// Behavior of the [`StubServer`].
+#[derive(Clone, Copy)]
+struct StubConfig {
+    /// `expires_in` of issued access tokens, in seconds.
+    expires_in: u64,
+
+    /// Delay before answering token requests.
+    token_delay: Duration,
+
+    /// HTTP status and body of token error responses, if token requests fail.
+    token_error: Option<(u16, &'static str)>,
+
+    /// HTTP status of Microsoft Graph API responses.
+    graph_status: u16,
+}
+
+impl Default for StubConfig {
+    fn default() -> Self {
+        Self {
+            expires_in: 3600,
+            token_delay: Duration::ZERO,
+            token_error: None,
+            graph_status: 202,
+        }
+    }
+}
+
+impl StubServer {
+    async fn start(config: StubConfig) -> Self {
+        let token_requests = Arc::new(AtomicUsize::new(0));
+        let graph_authorizations = Arc::new(Mutex::new(Vec::new()));
+
+        let url = common::serve({
+            let token_requests = token_requests.clone();
+            let graph_authorizations = graph_authorizations.clone();
+
+            move |request| {
+                let token_requests = token_requests.clone();
+                let graph_authorizations = graph_authorizations.clone();
+
+                async move {
+                    if request.path.ends_with("/oauth2/v2.0/token") {
+                        let number = token_requests.fetch_add(1, Ordering::SeqCst) + 1;
+                        tokio::time::sleep(config.token_delay).await;
+
+                        match config.token_error {
+                            Some((status, body)) => (status, body.to_string()),
+                            None => (
+                                200,
+                                format!(
+                                    r#"{{"token_type":"Bearer","expires_in":{},"access_token":"token-{number}"}}"#,
+                                    config.expires_in
+                                ),
+                            ),
+                        }
+                    } else {
+                        graph_authorizations
+                            .lock()
+                            .unwrap()
+                            .push(request.authorization.unwrap_or_default());
+
+                        (config.graph_status, String::new())
+                    }
+                }
+            }
+        })
+        .await;
+
+        Self {
+            url,
+            token_requests,
+            graph_authorizations,
+        }
+    }
+
+    fn mailer(&self) -> OutlookMailer {
+        OutlookMailer::builder("tenant".into(), "app".into(), SecretString::from("secret"))
+            .authority_host(&self.url)
+            .graph_base_url(&self.url)
+            .http_client(common::http_client())
+            .build_lazy()
+            .unwrap()
+    }
+
+    fn token_requests(&self) -> usize {
+        self.token_requests.load(Ordering::SeqCst)
+    }
+
+    fn graph_authorizations(&self) -> Vec<String> {
+        self.graph_authorizations.lock().unwrap().clone()
+    }
+}
+
+fn message() -> Message<'static> {
+    MessageBuilder::new()
+        .from("from@example.com")
+        .to("to@example.com")
+        .subject("Subject")
+        .text_body("Body")
+        .into_message()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reuses_valid_access_token() {
+    let server = StubServer::start(StubConfig::default()).await;
+    let mailer = server.mailer();
+
+    mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+    mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(server.token_requests(), 1);
+    assert_eq!(
+        server.graph_authorizations(),
+        ["Bearer token-1", "Bearer token-1"]
+    );
+}
+
+#[tokio::test]
+async fn refreshes_expiring_access_token() {
+    // A 2 second lifetime clamps the refresh margin to 1 second.
+    let server = StubServer::start(StubConfig {
+        expires_in: 2,
+        ..Default::default()
+    })
+    .await;
+    let mailer = server.mailer();
+
+    mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+    mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+    assert_eq!(server.token_requests(), 1);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(server.token_requests(), 2);
+    assert_eq!(
+        server.graph_authorizations(),
+        ["Bearer token-1", "Bearer token-1", "Bearer token-2"]
+    );
+}
+
+#[tokio::test]
+async fn retries_once_on_unauthorized() {
+    let server = StubServer::start(StubConfig {
+        graph_status: 401,
+        ..Default::default()
+    })
+    .await;
+    let mailer = server.mailer();
+
+    let error = mailer
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap_err();
+
+    let OutlookMailerError::Graph(error) = error else {
+        panic!("expected Microsoft Graph API error, got {error:?}");
+    };
+    assert_eq!(error.status, reqwest::StatusCode::UNAUTHORIZED);
+
+    assert_eq!(server.token_requests(), 2);
+    assert_eq!(
+        server.graph_authorizations(),
+        ["Bearer token-1", "Bearer token-2"]
+    );
+}
+
+#[tokio::test]
+async fn shares_single_in_flight_token_request() {
+    let server = StubServer::start(StubConfig {
+        token_delay: Duration::from_millis(200),
+        ..Default::default()
+    })
+    .await;
+    let mailer = server.mailer();
+
+    let senders: Vec<_> = (0..8)
+        .map(|_| {
+            let mailer = mailer.clone();
+            tokio::spawn(async move {
+                mailer
+                    .send_mail_with_options(message(), &Default::default())
+                    .await
+            })
+        })
+        .collect();
+
+    for sender in senders {
+        sender.await.unwrap().unwrap();
+    }
+
+    assert_eq!(server.token_requests(), 1);
+    assert_eq!(server.graph_authorizations(), ["Bearer token-1"; 8]);
+}
+
+#[tokio::test]
+async fn token_endpoint_server_error_is_transient() {
+    let server = StubServer::start(StubConfig {
+        token_error: Some((503, "<html><body>Service Unavailable</body></html>")),
+        ..Default::default()
+    })
+    .await;
+
+    let error = server.mailer().warm_up().await.unwrap_err();
+
+    assert!(
+        matches!(
+            error,
+            OutlookMailerError::RetrieveAccessToken(OutlookAccessTokenError::HttpStatus(status))
+                if status == reqwest::StatusCode::SERVICE_UNAVAILABLE
+        ),
+        "unexpected error: {error:?}"
+    );
+    assert!(error.is_transient());
+}
+
+#[tokio::test]
+async fn token_endpoint_throttling_is_transient() {
+    let server = StubServer::start(StubConfig {
+        token_error: Some((429, r#"{"error":"temporarily_unavailable"}"#)),
+        ..Default::default()
+    })
+    .await;
+
+    let error = server.mailer().warm_up().await.unwrap_err();
+
+    assert!(
+        matches!(
+            error,
+            OutlookMailerError::RetrieveAccessToken(OutlookAccessTokenError::HttpStatus(status))
+                if status == reqwest::StatusCode::TOO_MANY_REQUESTS
+        ),
+        "unexpected error: {error:?}"
+    );
+    assert!(error.is_transient());
+}
+
+#[tokio::test]
+async fn token_endpoint_oauth2_error_is_permanent() {
+    let server = StubServer::start(StubConfig {
+        token_error: Some((
+            401,
+            r#"{"error":"invalid_client","error_description":"Invalid client secret provided."}"#,
+        )),
+        ..Default::default()
+    })
+    .await;
+
+    let error = server.mailer().warm_up().await.unwrap_err();
+
+    assert!(
+        matches!(
+            &error,
+            OutlookMailerError::RetrieveAccessToken(OutlookAccessTokenError::OAuth2(message))
+                if message == "invalid_client: Invalid client secret provided."
+        ),
+        "unexpected error: {error:?}"
+    );
+    assert!(error.is_permanent());
+}
@@ -0,0 +1,113 @@
+//! Stub HTTP server for tests against a local Microsoft Identity service and Microsoft Graph API.
+
+// Each test binary only uses part of the stub.
+#![allow(dead_code)]
+
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+/// A request received by the stub server.
+pub struct StubRequest {
+    /// The request method, e.g. `POST`.
+    pub method: String,
+
+    /// The request path, e.g. `/tenant/oauth2/v2.0/token`.
+    pub path: String,
+
+    /// The `Authorization` header, if sent.
+    pub authorization: Option<String>,
+
+    /// The `Host` header, e.g. `127.0.0.1:1234`.
+    pub host: Option<String>,
+
+    /// The `Content-Range` header, if sent.
+    pub content_range: Option<String>,
+
+    /// The request body.
+    pub body: Vec<u8>,
+}
+
+/// Serve HTTP requests on a local port with `handler`, returning the HTTP status and JSON body of each response.
+///
+/// Returns the base URL of the server.
+pub async fn serve<F, Fut>(handler: F) -> String
+where
+    F: Fn(StubRequest) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = (u16, String)> + Send,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let handler = Arc::new(handler);
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let handler = handler.clone();
+
+            tokio::spawn(async move {
+                let mut stream = BufReader::new(stream);
+
+                let mut request_line = String::new();
+                stream.read_line(&mut request_line).await.unwrap();
+                let mut request_line = request_line.split(' ');
+                let method = request_line.next().unwrap_or_default().to_string();
+                let path = request_line.next().unwrap_or_default().to_string();
+
+                let mut content_length = 0;
+                let mut authorization = None;
+                let mut host = None;
+                let mut content_range = None;
+                loop {
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+
+                    let (name, value) = line.split_once(':').unwrap();
+                    match name.to_ascii_lowercase().as_str() {
+                        "content-length" => content_length = value.trim().parse().unwrap(),
+                        "authorization" => authorization = Some(value.trim().to_string()),
+                        "host" => host = Some(value.trim().to_string()),
+                        "content-range" => content_range = Some(value.trim().to_string()),
+                        _ => {}
+                    }
+                }
+
+                let mut body = vec![0; content_length];
+                stream.read_exact(&mut body).await.unwrap();
+
+                let (status, body) = handler(StubRequest {
+                    method,
+                    path,
+                    authorization,
+                    host,
+                    content_range,
+                    body,
+                })
+                .await;
+
+                let response = format!(
+                    "HTTP/1.1 {status} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                stream
+                    .get_mut()
+                    .write_all(response.as_bytes())
+                    .await
+                    .unwrap();
+            });
+        }
+    });
+
+    url
+}
+
+/// An HTTP client bypassing any system proxy, to reach the stub server.
+pub fn http_client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().unwrap()
+}
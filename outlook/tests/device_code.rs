@@ -0,0 +1,81 @@
+//! Device code sign-in of the `DelegatedTokenProvider`, tested against a stub Microsoft Identity service.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_mailer_outlook::{
+    DelegatedTokenProvider, InMemoryRefreshTokenStore, OutlookAccessTokenError, OutlookMailer,
+    OutlookMailerError,
+};
+
+mod common;
+
+/// Start a stub Microsoft Identity service, issuing device codes with the given lifetime and polling interval,
+/// and answering token requests with `authorization_pending`, as if the user never signs in.
+///
+/// Returns the server URL and the number of token requests.
+async fn start_pending_sign_in(expires_in: u64, interval: u64) -> (String, Arc<AtomicUsize>) {
+    let token_requests = Arc::new(AtomicUsize::new(0));
+
+    let url = common::serve({
+        let token_requests = token_requests.clone();
+
+        move |request| {
+            let token_requests = token_requests.clone();
+
+            async move {
+                if request.path.ends_with("/oauth2/v2.0/devicecode") {
+                    (
+                        200,
+                        format!(
+                            r#"{{"device_code":"device","user_code":"USER","verification_uri":"https://microsoft.com/devicelogin","message":"Sign in","expires_in":{expires_in},"interval":{interval}}}"#
+                        ),
+                    )
+                } else {
+                    token_requests.fetch_add(1, Ordering::SeqCst);
+
+                    (400, r#"{"error":"authorization_pending"}"#.to_string())
+                }
+            }
+        }
+    })
+    .await;
+
+    (url, token_requests)
+}
+
+#[tokio::test]
+async fn device_code_expires_locally() {
+    let (url, token_requests) = start_pending_sign_in(2, 1).await;
+
+    let prompts = Arc::new(Mutex::new(Vec::new()));
+    let token_provider = DelegatedTokenProvider::new(InMemoryRefreshTokenStore::default())
+        .device_code_prompt({
+            let prompts = prompts.clone();
+            move |prompt| prompts.lock().unwrap().push(prompt.user_code.clone())
+        });
+
+    let mailer =
+        OutlookMailer::builder_with_token_provider("tenant".into(), "app".into(), token_provider)
+            .authority_host(&url)
+            .graph_base_url(&url)
+            .http_client(common::http_client())
+            .build_lazy()
+            .unwrap();
+
+    let started_at = Instant::now();
+    let error = mailer.warm_up().await.unwrap_err();
+
+    assert!(
+        matches!(
+            &error,
+            OutlookMailerError::RetrieveAccessToken(OutlookAccessTokenError::OAuth2(message))
+                if message.starts_with("expired_token")
+        ),
+        "unexpected error: {error:?}"
+    );
+    assert!(started_at.elapsed() < Duration::from_secs(2));
+    assert_eq!(*prompts.lock().unwrap(), ["USER"]);
+    assert_eq!(token_requests.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,212 @@
+//! Sending of large messages through draft messages and upload sessions,
+//! tested against a stub Microsoft Graph API server.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_mailer_core::mail_send::mail_builder::MessageBuilder;
+use async_mailer_core::mail_send::smtp::message::{IntoMessage, Message};
+use async_mailer_outlook::{
+    FnTokenProvider, OutlookAccessToken, OutlookMailer, OutlookMailerError, TokenProviderError,
+};
+use secrecy::SecretString;
+
+mod common;
+
+const MIB: usize = 1024 * 1024;
+
+/// A request received by the stub server, without its body.
+#[derive(Debug, PartialEq)]
+struct Request {
+    method: String,
+    path: String,
+    content_range: Option<String>,
+    body_size: usize,
+}
+
+fn mailer(url: &str) -> OutlookMailer {
+    let token_provider = FnTokenProvider::new(|| async {
+        Ok::<_, TokenProviderError>(OutlookAccessToken::new(
+            SecretString::from("token"),
+            Instant::now() + Duration::from_secs(3600),
+        ))
+    });
+
+    OutlookMailer::builder_with_token_provider("tenant".into(), "app".into(), token_provider)
+        .graph_base_url(url)
+        .http_client(common::http_client())
+        .build_lazy()
+        .unwrap()
+}
+
+/// A message with attachments of the given sizes, too large for the `sendMail` endpoints.
+fn message(body: &str, attachment_sizes: &[usize]) -> Message<'static> {
+    let mut message = MessageBuilder::new()
+        .from("from@example.com")
+        .to("to@example.com")
+        .subject("Subject")
+        .text_body(body.to_string());
+
+    for (index, size) in attachment_sizes.iter().enumerate() {
+        message = message.attachment(
+            "application/octet-stream",
+            format!("attachment-{index}.bin"),
+            vec![b'a'; *size],
+        );
+    }
+
+    message.into_message().unwrap()
+}
+
+/// Serve a stub Microsoft Graph API, recording all requests,
+/// and failing attachment uploads with HTTP status `upload_status`.
+async fn serve(upload_status: u16) -> (String, Arc<Mutex<Vec<Request>>>) {
+    let requests = Arc::new(Mutex::new(Vec::new()));
+
+    let url = common::serve({
+        let requests = requests.clone();
+
+        move |request| {
+            let response = match (request.method.as_str(), request.path.as_str()) {
+                ("POST", path) if path.ends_with("/messages") => (201, r#"{"id":"draft"}"#.into()),
+                ("POST", path) if path.ends_with("/createUploadSession") => (
+                    201,
+                    format!(
+                        r#"{{"uploadUrl":"http://{host}/upload/{index}"}}"#,
+                        host = request.host.as_deref().unwrap(),
+                        // Distinguish upload sessions by the number of requests so far.
+                        index = requests.lock().unwrap().len(),
+                    ),
+                ),
+                ("PUT", _) => (upload_status, "{}".into()),
+                ("POST", path) if path.ends_with("/attachments") => (201, "{}".into()),
+                ("POST", path) if path.ends_with("/send") => (202, String::new()),
+                ("DELETE", _) => (204, String::new()),
+                _ => (404, String::new()),
+            };
+
+            requests.lock().unwrap().push(Request {
+                method: request.method,
+                path: request.path,
+                content_range: request.content_range,
+                body_size: request.body.len(),
+            });
+
+            async { response }
+        }
+    })
+    .await;
+
+    (url, requests)
+}
+
+fn request(method: &str, path: &str) -> (String, String) {
+    (method.to_string(), path.to_string())
+}
+
+#[tokio::test]
+async fn uploads_large_attachments_through_upload_sessions() {
+    let (url, requests) = serve(200).await;
+
+    let receipt = mailer(&url)
+        .send_mail_with_options(
+            message("Body", &[3 * MIB - 1, 3 * MIB, 7 * MIB]),
+            &Default::default(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(receipt.message_id.as_deref(), Some("draft"));
+
+    let requests = requests.lock().unwrap();
+    let draft = "/v1.0/users/from@example.com/messages/draft";
+
+    assert_eq!(
+        requests
+            .iter()
+            .map(|request| (request.method.clone(), request.path.clone()))
+            .collect::<Vec<_>>(),
+        [
+            request("POST", "/v1.0/users/from@example.com/messages"),
+            // Attachments below 3 MiB are added in a single request.
+            request("POST", &format!("{draft}/attachments")),
+            // Larger attachments are uploaded through an upload session.
+            request("POST", &format!("{draft}/attachments/createUploadSession")),
+            request("PUT", "/upload/2"),
+            request("POST", &format!("{draft}/attachments/createUploadSession")),
+            request("PUT", "/upload/4"),
+            request("PUT", "/upload/4"),
+            request("PUT", "/upload/4"),
+            request("POST", &format!("{draft}/send")),
+        ]
+    );
+
+    // Chunks are multiples of 320 KiB, with the last chunk holding the remainder.
+    let chunk_size = 10 * 320 * 1024;
+    let uploads = requests
+        .iter()
+        .filter(|request| request.method == "PUT")
+        .map(|request| (request.content_range.clone().unwrap(), request.body_size))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        uploads,
+        [
+            (format!("bytes 0-{}/{}", 3 * MIB - 1, 3 * MIB), 3 * MIB),
+            (
+                format!("bytes 0-{}/{}", chunk_size - 1, 7 * MIB),
+                chunk_size
+            ),
+            (
+                format!("bytes {chunk_size}-{}/{}", 2 * chunk_size - 1, 7 * MIB),
+                chunk_size
+            ),
+            (
+                format!("bytes {}-{}/{}", 2 * chunk_size, 7 * MIB - 1, 7 * MIB),
+                7 * MIB - 2 * chunk_size
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn deletes_draft_if_upload_fails() {
+    let (url, requests) = serve(400).await;
+
+    let result = mailer(&url)
+        .send_mail_with_options(message("Body", &[5 * MIB]), &Default::default())
+        .await;
+
+    assert!(matches!(result, Err(OutlookMailerError::Graph(_))));
+
+    let draft = "/v1.0/users/from@example.com/messages/draft";
+    assert_eq!(
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|request| (request.method.clone(), request.path.clone()))
+            .collect::<Vec<_>>(),
+        [
+            request("POST", "/v1.0/users/from@example.com/messages"),
+            request("POST", &format!("{draft}/attachments/createUploadSession")),
+            request("PUT", "/upload/1"),
+            // The draft is deleted instead of being sent.
+            request("DELETE", draft),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn rejects_large_message_body() {
+    let (url, requests) = serve(200).await;
+
+    let result = mailer(&url)
+        .send_mail_with_options(message(&"a".repeat(5 * MIB), &[]), &Default::default())
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(OutlookMailerError::MessageTooLarge(size)) if size > 5 * MIB
+    ));
+    assert!(requests.lock().unwrap().is_empty());
+}
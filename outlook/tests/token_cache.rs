@@ -0,0 +1,111 @@
+//! Token cache sharing between `OutlookMailer`s, tested against a stub Microsoft Graph API server.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_mailer_core::mail_send::mail_builder::MessageBuilder;
+use async_mailer_core::mail_send::smtp::message::{IntoMessage, Message};
+use async_mailer_outlook::{
+    FileTokenCache, FnTokenProvider, OutlookAccessToken, OutlookMailer, TokenProviderError,
+};
+use secrecy::SecretString;
+
+mod common;
+
+fn message() -> Message<'static> {
+    MessageBuilder::new()
+        .from("from@example.com")
+        .to("to@example.com")
+        .subject("Subject")
+        .text_body("Body")
+        .into_message()
+        .unwrap()
+}
+
+/// A mailer sharing the token cache file at `cache_path`,
+/// with a token provider issuing access tokens for `identity` and counting its calls.
+fn mailer(
+    url: &str,
+    cache_path: &std::path::Path,
+    identity: &'static str,
+    token_requests: Arc<AtomicUsize>,
+) -> OutlookMailer {
+    let token_provider = FnTokenProvider::new(move || {
+        let token_requests = token_requests.clone();
+
+        async move {
+            token_requests.fetch_add(1, Ordering::SeqCst);
+
+            Ok::<_, TokenProviderError>(OutlookAccessToken::new(
+                SecretString::from(format!("token-{identity}")),
+                Instant::now() + Duration::from_secs(3600),
+            ))
+        }
+    })
+    .cache_key(identity);
+
+    OutlookMailer::builder_with_token_provider("tenant".into(), "app".into(), token_provider)
+        .graph_base_url(url)
+        .http_client(common::http_client())
+        .token_cache(FileTokenCache::new(cache_path))
+        .build_lazy()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn providers_with_distinct_cache_keys_share_cache() {
+    let graph_authorizations = Arc::new(Mutex::new(Vec::new()));
+
+    let url = common::serve({
+        let graph_authorizations = graph_authorizations.clone();
+
+        move |request| {
+            graph_authorizations
+                .lock()
+                .unwrap()
+                .push(request.authorization.unwrap_or_default());
+
+            async { (202, String::new()) }
+        }
+    })
+    .await;
+
+    let cache_path = std::env::temp_dir().join(format!(
+        "async-mailer-outlook-token-cache-{}.json",
+        uuid::Uuid::new_v4().simple()
+    ));
+
+    let alice_token_requests = Arc::new(AtomicUsize::new(0));
+    let bob_token_requests = Arc::new(AtomicUsize::new(0));
+
+    let alice = mailer(&url, &cache_path, "alice", alice_token_requests.clone());
+    let bob = mailer(&url, &cache_path, "bob", bob_token_requests.clone());
+
+    alice
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+    bob.send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+
+    // A new mailer for the same identity reuses that identity's cached token.
+    mailer(&url, &cache_path, "alice", alice_token_requests.clone())
+        .send_mail_with_options(message(), &Default::default())
+        .await
+        .unwrap();
+
+    assert_eq!(alice_token_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(bob_token_requests.load(Ordering::SeqCst), 1);
+    assert_eq!(
+        *graph_authorizations.lock().unwrap(),
+        [
+            "Bearer token-alice",
+            "Bearer token-bob",
+            "Bearer token-alice"
+        ]
+    );
+
+    tokio::fs::remove_file(&cache_path).await.unwrap();
+}
@@ -0,0 +1,310 @@
+//! Mail transactions of the [`SmtpMailer`](crate::SmtpMailer), reporting the outcome per recipient.
+
+#[cfg(feature = "tracing")]
+use tracing::warn;
+
+use async_mailer_core::mail_send::smtp::message::Message;
+use smtp_proto::{EXT_8BIT_MIME, EXT_DSN, EXT_SIZE, EXT_SMTP_UTF8};
+
+use crate::pool::{self, PooledSession};
+use crate::{SmtpMailerError, SmtpReply, SmtpSendOptions};
+
+/// Whether the SMTP server accepted or rejected a recipient.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SmtpRecipientStatus {
+    /// The server accepted the recipient with `RCPT TO`.
+    Accepted,
+
+    /// The server rejected the recipient with the given reply.
+    Rejected(SmtpReply),
+}
+
+/// The outcome of a single recipient of a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SmtpRecipientOutcome {
+    /// The envelope recipient address.
+    pub address: String,
+
+    /// Whether the server accepted or rejected the recipient.
+    pub status: SmtpRecipientStatus,
+}
+
+/// Per-recipient outcomes of a message,
+/// returned by [`SmtpMailer::send_mail_with_options`](crate::SmtpMailer::send_mail_with_options),
+/// or carried by an [`SmtpMailerError::RecipientsRejected`] error.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SmtpSendReport {
+    /// The outcome of each envelope recipient, in the order of the message's recipients.
+    pub recipients: Vec<SmtpRecipientOutcome>,
+}
+
+impl SmtpSendReport {
+    /// The addresses of the recipients accepted by the server.
+    pub fn accepted(&self) -> impl Iterator<Item = &str> {
+        self.recipients
+            .iter()
+            .filter(|recipient| recipient.status == SmtpRecipientStatus::Accepted)
+            .map(|recipient| recipient.address.as_str())
+    }
+
+    /// The addresses of the recipients rejected by the server, along with the server's reply.
+    pub fn rejected(&self) -> impl Iterator<Item = (&str, &SmtpReply)> {
+        self.recipients
+            .iter()
+            .filter_map(|recipient| match &recipient.status {
+                SmtpRecipientStatus::Accepted => None,
+                SmtpRecipientStatus::Rejected(reply) => Some((recipient.address.as_str(), reply)),
+            })
+    }
+}
+
+/// Error of a mail transaction, returned by [`send`].
+pub(crate) struct DeliveryError {
+    pub(crate) error: SmtpMailerError,
+
+    /// Whether the message data may have been sent, so the message may have been accepted by the server.
+    data_sent: bool,
+}
+
+impl DeliveryError {
+    fn before_data(error: SmtpMailerError) -> Self {
+        Self {
+            error,
+            data_sent: false,
+        }
+    }
+
+    /// Whether the session failed before the message data was sent,
+    /// so the transaction can safely be retried on a new session.
+    pub(crate) fn is_session_lost_before_data(&self) -> bool {
+        !self.data_sent
+            && matches!(&self.error, SmtpMailerError::Send(error) if pool::is_connection_error(error))
+    }
+}
+
+/// Run a mail transaction on the session: `MAIL FROM`, `RCPT TO` for each recipient, and `DATA`.
+///
+/// ESMTP parameters are added according to the server's `EHLO` capabilities:
+/// `SIZE` with the message size, `BODY=8BITMIME` for 8-bit message data,
+/// `SMTPUTF8` for internationalized addresses, and the requested DSN parameters.
+///
+/// Recipients rejected by the server are recorded in the report, rather than aborting the transaction.
+/// The message data is only sent if all recipients were accepted,
+/// or, with partial delivery, if at least one recipient was accepted.
+///
+/// # Errors
+///
+/// Returns an [`SmtpMailerError::MessageTooLarge`] error if the message exceeds the server's maximum message size.
+///
+/// Returns an [`SmtpMailerError::MissingSmtpUtf8`] error if the message requires `SMTPUTF8`,
+/// but the server does not offer it.
+///
+/// Returns an [`SmtpMailerError::RecipientsRejected`] error if recipients were rejected
+/// and the message was therefore not sent.
+///
+/// Returns an [`SmtpMailerError::Send`] error if the server rejected the sender or the message data,
+/// or if the session failed.
+///
+/// Each error is wrapped in a [`DeliveryError`], recording whether the message data may have been sent.
+pub(crate) async fn send(
+    session: &mut PooledSession,
+    message: &Message<'_>,
+    options: &SmtpSendOptions,
+) -> Result<SmtpSendReport, DeliveryError> {
+    let capabilities = &session.capabilities;
+    let size = message.body.len();
+
+    // A maximum size of 0 means that the server does not announce a fixed limit (RFC 1870).
+    if capabilities.has_capability(EXT_SIZE) && capabilities.size > 0 && size > capabilities.size {
+        return Err(DeliveryError::before_data(
+            SmtpMailerError::MessageTooLarge {
+                size,
+                max_size: capabilities.size,
+            },
+        ));
+    }
+
+    let mut mail_from_parameters = message.mail_from.parameters.clone();
+
+    if capabilities.has_capability(EXT_SIZE) {
+        mail_from_parameters.add(("SIZE".to_string(), size.to_string()));
+    }
+
+    if capabilities.has_capability(EXT_8BIT_MIME) && !message.body.is_ascii() {
+        mail_from_parameters.add(("BODY", "8BITMIME"));
+    }
+
+    if requires_smtp_utf8(message) {
+        if !capabilities.has_capability(EXT_SMTP_UTF8) {
+            return Err(DeliveryError::before_data(SmtpMailerError::MissingSmtpUtf8));
+        }
+
+        mail_from_parameters.add("SMTPUTF8");
+    }
+
+    let dsn = options.requests_dsn() && capabilities.has_capability(EXT_DSN);
+
+    #[cfg(feature = "tracing")]
+    if options.requests_dsn() && !dsn {
+        warn!(
+            "SMTP host does not offer DSN; sending without delivery status notification parameters"
+        );
+    }
+
+    if dsn {
+        if let Some(dsn_return) = options.dsn_return {
+            mail_from_parameters.add(("RET", dsn_return.as_str()));
+        }
+
+        if let Some(dsn_envelope_id) = &options.dsn_envelope_id {
+            mail_from_parameters.add(("ENVID".to_string(), xtext(dsn_envelope_id)));
+        }
+    }
+
+    let dsn_notify = options.dsn_notify_value().filter(|_| dsn);
+
+    session
+        .client
+        .mail_from(message.mail_from.email.as_ref(), &mail_from_parameters)
+        .await
+        .map_err(|error| DeliveryError::before_data(SmtpMailerError::Send(error)))?;
+
+    let mut report = SmtpSendReport::default();
+
+    for recipient in &message.rcpt_to {
+        let mut rcpt_to_parameters = recipient.parameters.clone();
+
+        if let Some(dsn_notify) = &dsn_notify {
+            rcpt_to_parameters.add(("NOTIFY".to_string(), dsn_notify.clone()));
+        }
+
+        let status = match session
+            .client
+            .rcpt_to(recipient.email.as_ref(), &rcpt_to_parameters)
+            .await
+        {
+            Ok(()) => SmtpRecipientStatus::Accepted,
+            Err(error) if pool::is_connection_error(&error) => {
+                return Err(DeliveryError::before_data(SmtpMailerError::Send(error)))
+            }
+            Err(error) => match SmtpReply::from_error(&error) {
+                Some(reply) => SmtpRecipientStatus::Rejected(reply),
+                None => return Err(DeliveryError::before_data(SmtpMailerError::Send(error))),
+            },
+        };
+
+        report.recipients.push(SmtpRecipientOutcome {
+            address: recipient.email.to_string(),
+            status,
+        });
+    }
+
+    let any_rejected = report.rejected().next().is_some();
+    let any_accepted = report.accepted().next().is_some();
+
+    if any_rejected && !(options.partial_delivery && any_accepted) {
+        return Err(DeliveryError::before_data(
+            SmtpMailerError::RecipientsRejected(report),
+        ));
+    }
+
+    session
+        .client
+        .data(message.body.as_ref())
+        .await
+        .map_err(|error| DeliveryError {
+            error: SmtpMailerError::Send(error),
+            data_sent: true,
+        })?;
+
+    Ok(report)
+}
+
+/// Whether the message requires `SMTPUTF8` (RFC 6531),
+/// i.e. if an envelope address or the message header contains non-ASCII characters.
+fn requires_smtp_utf8(message: &Message<'_>) -> bool {
+    let header_end = message
+        .body
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .unwrap_or(message.body.len());
+
+    !message.mail_from.email.is_ascii()
+        || message
+            .rcpt_to
+            .iter()
+            .any(|recipient| !recipient.email.is_ascii())
+        || !message.body[..header_end].is_ascii()
+}
+
+/// Encode a DSN parameter value as `xtext` (RFC 3461, section 4),
+/// escaping `+`, `=` and characters outside of printable ASCII as `+XX`.
+fn xtext(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+
+    for byte in value.bytes() {
+        match byte {
+            b'!'..=b'~' if byte != b'+' && byte != b'=' => encoded.push(char::from(byte)),
+            _ => encoded.push_str(&format!("+{byte:02X}")),
+        }
+    }
+
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_xtext() {
+        assert_eq!(xtext("order-1234@example.com"), "order-1234@example.com");
+        assert_eq!(xtext("a+b=c"), "a+2Bb+3Dc");
+        assert_eq!(xtext("a b\t"), "a+20b+09");
+        assert_eq!(xtext("ä"), "+C3+A4");
+    }
+
+    #[test]
+    fn requires_smtp_utf8_for_non_ascii_addresses() {
+        let body = "Subject: Test\r\n\r\nHello.\r\n";
+
+        assert!(!requires_smtp_utf8(
+            &Message::empty()
+                .from("sender@example.com")
+                .to("recipient@example.com")
+                .body(body.as_bytes())
+        ));
+        assert!(requires_smtp_utf8(
+            &Message::empty()
+                .from("sénder@example.com")
+                .to("recipient@example.com")
+                .body(body.as_bytes())
+        ));
+        assert!(requires_smtp_utf8(
+            &Message::empty()
+                .from("sender@example.com")
+                .to("recipient@example.com")
+                .to("empfänger@example.com")
+                .body(body.as_bytes())
+        ));
+    }
+
+    #[test]
+    fn requires_smtp_utf8_for_non_ascii_header_only() {
+        let message = |body: &'static str| {
+            Message::empty()
+                .from("sender@example.com")
+                .to("recipient@example.com")
+                .body(body.as_bytes())
+        };
+
+        assert!(requires_smtp_utf8(&message(
+            "Subject: Grüße\r\n\r\nHello.\r\n"
+        )));
+        assert!(!requires_smtp_utf8(&message(
+            "Subject: Greetings\r\n\r\nGrüße.\r\n"
+        )));
+    }
+}
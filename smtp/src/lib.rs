@@ -101,32 +101,218 @@
 //!
 //! - `tracing`: Enable debug and error logging using the [`tracing`](https://docs.rs/crate/tracing) crate.
 //!   All relevant functions are instrumented.
-//! - `clap`: Implement [`clap::ValueEnum`](https://docs.rs/clap/latest/clap/trait.ValueEnum.html) for [`SmtpInvalidCertsPolicy`].
-//!   This allows for easily configured CLI options like `--invalid-certs <allow|deny>`.
+//! - `clap`: Implement [`clap::ValueEnum`](https://docs.rs/clap/latest/clap/trait.ValueEnum.html) for [`SmtpInvalidCertsPolicy`],
+//!   [`SmtpTlsMode`] and [`SmtpAuthMechanism`].
+//!   This allows for easily configured CLI options like `--invalid-certs <allow|deny>`
+//!   or `--tls-mode <implicit|start-tls|start-tls-opportunistic|plaintext>`.
+//! - `dkim`: Enable DKIM signing of outgoing messages with [`SmtpDkimSigner`],
+//!   using the [`mail-auth`](https://docs.rs/mail-auth) crate.
 //!
 //! Default: `tracing`.
 //!
-//! ## Roadmap
+//! ## Builder
 //!
-//! DKIM support is planned to be implemented on the [`SmtpMailer`].
+//! [`SmtpMailer::new`] connects with implicit TLS and authenticates with a username and password.
+//! Use [`SmtpMailer::builder`] to configure the connection timeout, the `EHLO` domain,
+//! the local IP address to bind to, the [`SmtpTlsMode`], and the preferred authentication mechanisms,
+//! or to omit credentials for unauthenticated relays:
+//!
+//! ```no_run
+//! # fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use std::net::{IpAddr, Ipv4Addr};
+//! # use std::time::Duration;
+//! # use async_mailer_smtp::{SmtpMailer, SmtpTlsMode};
+//! let mailer = SmtpMailer::builder("relay.example.com", 587)
+//!     .tls_mode(SmtpTlsMode::StartTls)
+//!     // Must match the reverse DNS name of the local IP address.
+//!     .helo_host("app.example.com")
+//!     .local_ip(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10)))
+//!     .timeout(Duration::from_secs(10))
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Private certificate authorities and mutual TLS
+//!
+//! Rather than allowing invalid certificates, trust the root certificate of a private certificate authority
+//! with [`SmtpMailerBuilder::root_certificate`]. Additionally, the server's public key may be pinned with
+//! [`SmtpMailerBuilder::pin_spki_sha256`], and a client certificate presented to relays requiring mutual TLS
+//! with [`SmtpMailerBuilder::client_certificate`]:
+//!
+//! ```no_run
+//! # fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_smtp::{SmtpCertificate, SmtpClientCertificate, SmtpMailer, SmtpTlsMode};
+//! let mailer = SmtpMailer::builder("relay.internal.example.com", 587)
+//!     .tls_mode(SmtpTlsMode::StartTls)
+//!     .root_certificate(SmtpCertificate::from_pem(&std::fs::read("ca.pem")?)?)
+//!     .client_certificate(SmtpClientCertificate::from_pem(
+//!         &std::fs::read("client.pem")?,
+//!         &std::fs::read("client.key")?,
+//!     )?)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## OAuth2 authentication
+//!
+//! Microsoft 365 and Gmail are phasing out password authentication over SMTP.
+//! Use [`SmtpMailerBuilder::oauth2`] to authenticate with `XOAUTH2` or `OAUTHBEARER` instead,
+//! retrieving access tokens from an [`SmtpTokenSource`], such as an [`FnSmtpTokenSource`]:
+//!
+//! ```no_run
+//! # fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_smtp::{FnSmtpTokenSource, SmtpMailer, SmtpTlsMode, SmtpTokenSourceError};
+//! let token_source = FnSmtpTokenSource::new(|| async {
+//!     // Retrieve an access token with the `https://outlook.office.com/SMTP.Send` scope.
+//!     Ok::<_, SmtpTokenSourceError>(secrecy::SecretString::from("<access token>"))
+//! });
+//!
+//! let mailer = SmtpMailer::builder("smtp.office365.com", 587)
+//!     .tls_mode(SmtpTlsMode::StartTls)
+//!     .oauth2("sender@example.com", token_source)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## Connection pooling
+//!
+//! The [`SmtpMailer`] keeps authenticated SMTP sessions open after sending,
+//! and reuses them for subsequent messages, saving the TCP connection, TLS handshake, `EHLO` and `AUTH`
+//! round trips per message. Clones of an `SmtpMailer` share the same pool.
+//!
+//! Idle sessions are health-checked with `NOOP` before reuse, and transparently replaced by a new connection
+//! if the server has dropped them. If a reused session fails before the message data is sent,
+//! the mail transaction is retried once on a new connection.
+//! Sessions are reset with `RSET` after a rejected mail transaction.
+//! Configure the pool size, idle timeout and maximum number of messages per connection
+//! with [`SmtpMailerBuilder::pool_config`] and [`SmtpPoolConfig`].
+//!
+//! ## Per-recipient delivery results
+//!
+//! By default, the message is not sent if the server rejects any of its recipients,
+//! and an [`SmtpMailerError::RecipientsRejected`] error reports the outcome of each recipient.
+//! Use [`SmtpMailer::send_mail_with_options`] with [`SmtpSendOptions::partial_delivery`]
+//! to send the message to the accepted recipients instead:
+//!
+//! ```no_run
+//! # async fn test(
+//! #     mailer: async_mailer_smtp::SmtpMailer,
+//! #     message: async_mailer_core::mail_send::smtp::message::Message<'_>,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_smtp::SmtpSendOptions;
+//! let report = mailer
+//!     .send_mail_with_options(message, &SmtpSendOptions::new().partial_delivery(true))
+//!     .await?;
+//!
+//! for (address, reply) in report.rejected() {
+//!     eprintln!("{address} was rejected: {reply}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## ESMTP extensions
+//!
+//! The [`SmtpMailer`] inspects the server's `EHLO` capabilities before each mail transaction:
+//!
+//! - With `SIZE`, messages exceeding the server's maximum message size are refused
+//!   with an [`SmtpMailerError::MessageTooLarge`] error before they are transmitted.
+//! - With `8BITMIME`, messages containing 8-bit data are announced with `BODY=8BITMIME`.
+//! - Messages with internationalized addresses or headers are sent with `SMTPUTF8`,
+//!   or refused with an [`SmtpMailerError::MissingSmtpUtf8`] error if the server does not offer it.
+//! - With `DSN`, delivery status notifications are requested as configured per message
+//!   with [`SmtpSendOptions::dsn_notify`], [`SmtpSendOptions::dsn_return`] and [`SmtpSendOptions::dsn_envelope_id`].
+//!   Without `DSN`, the message is sent without these parameters.
+//!
+//! ```no_run
+//! # async fn test(
+//! #     mailer: async_mailer_smtp::SmtpMailer,
+//! #     message: async_mailer_core::mail_send::smtp::message::Message<'_>,
+//! # ) -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_smtp::{SmtpDsnNotify, SmtpDsnReturn, SmtpSendOptions};
+//! let options = SmtpSendOptions::new()
+//!     .dsn_notify([SmtpDsnNotify::Success, SmtpDsnNotify::Failure])
+//!     .dsn_return(SmtpDsnReturn::Headers)
+//!     .dsn_envelope_id("order-4711");
+//!
+//! mailer.send_mail_with_options(message, &options).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! ## DKIM signing
+//!
+//! With the `dkim` feature, messages are DKIM-signed before sending, with RSA-SHA256 or Ed25519-SHA256 keys.
+//! Register one or more signers with `SmtpMailerBuilder::dkim_signer`.
+//! Each message is signed with the signers whose domain is aligned with the message's `From:` domain.
+//! Messages without an aligned signer are sent unsigned, logging a warning with the `tracing` feature,
+//! or are rejected if `SmtpMailerBuilder::dkim_require_signature` is enabled.
+//!
+//! ```no_run
+//! # #[cfg(feature = "dkim")]
+//! # fn test() -> Result<(), Box<dyn std::error::Error>> {
+//! # use async_mailer_smtp::{SmtpDkimSigner, SmtpMailer};
+//! let mailer = SmtpMailer::builder("smtp.example.com", 465)
+//!     .dkim_signer(SmtpDkimSigner::rsa_sha256_from_pem(
+//!         "example.com",
+//!         "rsa2026",
+//!         &std::fs::read("dkim-rsa.key")?,
+//!     )?)
+//!     .dkim_signer(SmtpDkimSigner::ed25519_from_pem(
+//!         "example.com",
+//!         "ed2026",
+//!         &std::fs::read("dkim-ed25519.key")?,
+//!     )?)
+//!     .build()?;
+//! # Ok(())
+//! # }
+//! ```
 
+use std::net::IpAddr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use async_trait::async_trait;
 
-#[cfg(feature = "clap")]
-use clap;
+mod delivery;
+#[cfg(feature = "dkim")]
+mod dkim;
+mod oauth2;
+mod options;
+mod pool;
+mod reply;
+mod session;
+mod tls;
 
 use secrecy::{ExposeSecret, SecretString};
 
 #[cfg(feature = "tracing")]
-use tracing::{error, info, instrument};
+use tracing::{debug, error, info, instrument, warn};
 
 use async_mailer_core::mail_send::{self, smtp::message::Message, SmtpClientBuilder};
-use async_mailer_core::{util, ArcMailer, BoxMailer, DynMailer, DynMailerError, Mailer};
+use async_mailer_core::{ArcMailer, BoxMailer, DynMailer, DynMailerError, Mailer};
 
-/// Error returned by [`SmtpMailer::new`] and [`SmtpMailer::send_mail`].
+#[cfg(feature = "tracing")]
+use async_mailer_core::util;
+
+pub use delivery::{SmtpRecipientOutcome, SmtpRecipientStatus, SmtpSendReport};
+#[cfg(feature = "dkim")]
+pub use dkim::{SmtpDkimCanonicalization, SmtpDkimError, SmtpDkimSigner};
+pub use oauth2::{FnSmtpTokenSource, SmtpTokenSource, SmtpTokenSourceError};
+pub use options::{SmtpDsnNotify, SmtpDsnReturn, SmtpSendOptions};
+pub use pool::SmtpPoolConfig;
+pub use reply::{SmtpEnhancedStatusCode, SmtpReply};
+pub use tls::{SmtpCertificate, SmtpCertificateError, SmtpClientCertificate};
+
+use delivery::DeliveryError;
+use oauth2::OAuth2Authentication;
+use pool::SmtpPool;
+use tls::TlsConfig;
+
+/// Error returned by [`SmtpMailer::new`], [`SmtpMailerBuilder::build`] and [`SmtpMailer::send_mail`].
 #[derive(Debug, thiserror::Error)]
 pub enum SmtpMailerError {
     /// Failed to build the SMTP client.
@@ -137,18 +323,121 @@ pub enum SmtpMailerError {
     #[error("could not connect to SMTP host: {0}")]
     Connect(mail_send::Error),
 
+    /// Could not retrieve an OAuth2 access token from the configured [`SmtpTokenSource`].
+    #[error("could not retrieve OAuth2 access token: {0}")]
+    AccessToken(SmtpTokenSourceError),
+
+    /// The SMTP host does not offer STARTTLS, which is required by [`SmtpTlsMode::StartTls`].
+    #[error("SMTP host does not offer STARTTLS, which is required by the configured TLS mode")]
+    MissingStartTls,
+
+    /// Could not DKIM-sign the message.
+    /// (Crate feature `dkim` only.)
+    #[cfg(feature = "dkim")]
+    #[error("could not DKIM-sign SMTP mail: {0}")]
+    DkimSign(mail_auth::Error),
+
+    /// No DKIM signer is aligned with the message's `From:` domain,
+    /// and [`SmtpMailerBuilder::dkim_require_signature`] is enabled. The message was not sent.
+    /// (Crate feature `dkim` only.)
+    #[cfg(feature = "dkim")]
+    #[error("no DKIM signer for the message's From domain")]
+    MissingDkimSigner,
+
+    /// The message exceeds the maximum message size announced by the SMTP host with the `SIZE` extension.
+    /// The message was not sent.
+    #[error(
+        "message of {size} bytes exceeds the SMTP host's maximum message size of {max_size} bytes"
+    )]
+    MessageTooLarge {
+        /// Size of the message in bytes.
+        size: usize,
+
+        /// Maximum message size in bytes, as announced by the SMTP host.
+        max_size: usize,
+    },
+
+    /// The message has internationalized addresses or headers, requiring `SMTPUTF8`,
+    /// which is not offered by the SMTP host. The message was not sent.
+    #[error("message requires SMTPUTF8, which is not offered by the SMTP host")]
+    MissingSmtpUtf8,
+
+    /// The SMTP host rejected recipients, and the message was therefore not sent.
+    ///
+    /// The wrapped [`SmtpSendReport`] holds the outcome of each recipient.
+    /// Use [`SmtpSendOptions::partial_delivery`] to send the message to the accepted recipients instead.
+    #[error(
+        "SMTP host rejected {rejected} of {total} recipients",
+        rejected = .0.rejected().count(),
+        total = .0.recipients.len()
+    )]
+    RecipientsRejected(SmtpSendReport),
+
     /// Could not send SMTP mail.
     #[error("could not send SMTP mail: {0}")]
     Send(mail_send::Error),
 }
 
+impl SmtpMailerError {
+    /// The SMTP server reply which caused the error, holding the reply code,
+    /// the enhanced status code (RFC 3463) if sent by the server, and the reply text.
+    ///
+    /// Returns `Some` if the server rejected a command while connecting, authenticating or sending.
+    /// For [`SmtpMailerError::RecipientsRejected`], returns the reply rejecting the first rejected recipient.
+    /// Otherwise returns `None`.
+    pub fn reply(&self) -> Option<SmtpReply> {
+        match self {
+            Self::Connect(error) | Self::Send(error) => SmtpReply::from_error(error),
+            Self::RecipientsRejected(report) => {
+                report.rejected().next().map(|(_, reply)| reply.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether sending the message may succeed if retried later.
+    ///
+    /// Transient errors are transient negative completion replies (`4xx`), e.g. greylisting or a full mailbox,
+    /// as well as I/O errors, timeouts, unparseable replies and failures to retrieve an OAuth2 access token.
+    /// Rejected recipients are transient if all rejections are transient.
+    ///
+    /// All other errors are permanent, see [`SmtpMailerError::is_permanent`].
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Connect(error) | Self::Send(error) => match SmtpReply::from_error(error) {
+                Some(reply) => reply.is_transient(),
+                None => matches!(
+                    error,
+                    mail_send::Error::Io(_)
+                        | mail_send::Error::Timeout
+                        | mail_send::Error::UnparseableReply
+                ),
+            },
+            Self::RecipientsRejected(report) => {
+                report.rejected().all(|(_, reply)| reply.is_transient())
+            }
+            Self::AccessToken(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether sending the message will fail again if retried unchanged.
+    ///
+    /// Permanent errors are permanent negative completion replies (`5xx`), e.g. an unknown recipient,
+    /// as well as TLS and configuration errors. Every error is either transient or permanent.
+    pub fn is_permanent(&self) -> bool {
+        !self.is_transient()
+    }
+}
+
 /// Pass to [`SmtpMailer::new`] to either allow or deny invalid SMTP certificates.
 ///
 /// This option allows to perform tests or local development work against
 /// SMTP development servers like MailHog or MailPit, while using a self-signed certificate.
 ///
 /// **Never use [`SmtpInvalidCertsPolicy::Allow`] in production!**
-// TODO: derive Clap ValueEnum
+/// To connect to servers with certificates issued by a private certificate authority,
+/// use [`SmtpMailerBuilder::root_certificate`] instead.
 #[derive(Clone, Debug, Default)]
 #[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
 pub enum SmtpInvalidCertsPolicy {
@@ -164,6 +453,65 @@ pub enum SmtpInvalidCertsPolicy {
     Deny,
 }
 
+/// Pass to [`SmtpMailerBuilder::tls_mode`] to select how the SMTP connection is secured.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum SmtpTlsMode {
+    /// Connect with TLS from the start, commonly on port 465.
+    ///
+    /// This variant is the [`Default`].
+    #[default]
+    Implicit,
+
+    /// Connect in plaintext and upgrade the connection with STARTTLS, commonly on port 587.
+    ///
+    /// Fails with [`SmtpMailerError::MissingStartTls`] if the server does not offer STARTTLS.
+    StartTls,
+
+    /// Connect in plaintext and upgrade the connection with STARTTLS if the server offers it,
+    /// otherwise continue in plaintext.
+    ///
+    /// **Vulnerable to downgrade attacks.** Prefer [`SmtpTlsMode::StartTls`].
+    StartTlsOpportunistic,
+
+    /// Connect in plaintext, without TLS, e.g. to a local relay on port 25.
+    ///
+    /// **Credentials and messages are sent unencrypted.**
+    Plaintext,
+}
+
+/// SMTP authentication mechanism, passed to [`SmtpMailerBuilder::auth_mechanisms`]
+/// to restrict and order the mechanisms tried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+pub enum SmtpAuthMechanism {
+    /// `AUTH PLAIN`, sending username and password in a single step.
+    Plain,
+
+    /// `AUTH LOGIN`, sending username and password in response to separate server challenges.
+    Login,
+
+    /// `AUTH XOAUTH2`, sending an OAuth2 access token, as supported by Microsoft 365 and Gmail.
+    #[cfg_attr(feature = "clap", value(name = "xoauth2"))]
+    XOAuth2,
+
+    /// `AUTH OAUTHBEARER` (RFC 7628), sending an OAuth2 access token.
+    #[cfg_attr(feature = "clap", value(name = "oauthbearer"))]
+    OAuthBearer,
+}
+
+impl SmtpAuthMechanism {
+    /// The `smtp-proto` bit flag of the mechanism.
+    fn to_smtp_proto(self) -> u64 {
+        match self {
+            Self::Plain => smtp_proto::AUTH_PLAIN,
+            Self::Login => smtp_proto::AUTH_LOGIN,
+            Self::XOAuth2 => smtp_proto::AUTH_XOAUTH2,
+            Self::OAuthBearer => smtp_proto::AUTH_OAUTHBEARER,
+        }
+    }
+}
+
 /// An SMTP mailer client, implementing the [`async_mailer_core::Mailer`](https://docs.rs/async-mailer/latest/async_mailer/trait.Mailer.html)
 /// and [`async_mailer_core::DynMailer`](https://docs.rs/async-mailer/latest/async_mailer/trait.DynMailer.html) traits
 /// to be used as generic mailer or runtime-pluggable trait object.
@@ -171,9 +519,20 @@ pub enum SmtpInvalidCertsPolicy {
 /// An abstraction over [`mail-send`](https://docs.rs/mail-send), sending mail via an SMTP connection.
 ///
 /// Self-signed certificates can optionally be accepted, to use the SMTP mailer in development while using the Outlook mailer in production.
+///
+/// Authenticated SMTP sessions are pooled and reused across messages, see [`SmtpPoolConfig`].
+/// Clones share the same connection pool.
 #[derive(Clone)]
 pub struct SmtpMailer {
     inner: SmtpClientBuilder<String>,
+    tls_mode: SmtpTlsMode,
+    auth_mechanisms: Vec<SmtpAuthMechanism>,
+    oauth2: Option<Arc<OAuth2Authentication>>,
+    #[cfg(feature = "dkim")]
+    dkim_signers: Vec<Arc<SmtpDkimSigner>>,
+    #[cfg(feature = "dkim")]
+    dkim_require_signature: bool,
+    pool: Arc<SmtpPool>,
 }
 
 impl std::fmt::Debug for SmtpMailer {
@@ -182,8 +541,18 @@ impl std::fmt::Debug for SmtpMailer {
     }
 }
 
+/// Default timeout for establishing an SMTP session and for each SMTP command.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl SmtpMailer {
-    /// Create a new SMTP mailer client.
+    /// Create an [`SmtpMailerBuilder`] to configure a new SMTP mailer client.
+    pub fn builder(host: impl Into<String>, port: u16) -> SmtpMailerBuilder {
+        SmtpMailerBuilder::new(host, port)
+    }
+
+    /// Create a new SMTP mailer client, connecting with implicit TLS and authenticating with username and password.
+    ///
+    /// Use [`SmtpMailer::builder`] to configure further options.
     ///
     /// # Errors
     ///
@@ -197,16 +566,10 @@ impl SmtpMailer {
         user: String,
         password: SecretString,
     ) -> Result<Self, SmtpMailerError> {
-        let mut smtp_client = SmtpClientBuilder::new(host, port)
-            .map_err(SmtpMailerError::Build)?
-            .credentials((user, password.expose_secret().into()))
-            .timeout(Duration::from_secs(30));
-
-        if matches!(invalid_certs, SmtpInvalidCertsPolicy::Allow) {
-            smtp_client = smtp_client.allow_invalid_certs();
-        }
-
-        Ok(Self { inner: smtp_client })
+        Self::builder(host, port)
+            .invalid_certs(invalid_certs)
+            .credentials(user, password)
+            .build()
     }
 
     /// Create a new SMTP mailer client as dynamic `async_mailer::BoxMailer`.
@@ -254,55 +617,411 @@ impl SmtpMailer {
             password,
         )?))
     }
-}
 
-// == Mailer ==
-
-#[async_trait]
-impl Mailer for SmtpMailer {
-    type Error = SmtpMailerError;
-
-    /// Send the prepared MIME message via an SMTP connection, using the previously configured credentials.
+    /// Send the prepared MIME message via an SMTP connection, applying per-message [`SmtpSendOptions`].
+    ///
+    /// Returns an [`SmtpSendReport`], holding the outcome of each recipient.
+    /// E.g., use [`SmtpSendOptions::partial_delivery`] to send the message to the accepted recipients
+    /// if some recipients are rejected, rather than failing.
     ///
     /// # Errors
     ///
     /// Returns an [`SmtpMailerError::Connect`] error if a connection to the SMTP server cannot be established.
     ///
+    /// Returns an [`SmtpMailerError::MissingStartTls`] error if STARTTLS is required but not offered by the server.
+    ///
+    /// Returns an [`SmtpMailerError::AccessToken`] error if an OAuth2 access token cannot be retrieved.
+    ///
+    /// Returns an [`SmtpMailerError::MessageTooLarge`] error if the message exceeds the server's maximum message size.
+    ///
+    /// Returns an [`SmtpMailerError::MissingSmtpUtf8`] error if the message requires `SMTPUTF8`,
+    /// but the server does not offer it.
+    ///
+    /// Returns an [`SmtpMailerError::RecipientsRejected`] error if the server rejected recipients,
+    /// and the message was therefore not sent.
+    ///
     /// Returns an [`SmtpMailerError::Send`] error if the connection was established but sending the e-mail message failed.
-    async fn send_mail(&self, message: Message<'_>) -> Result<(), Self::Error> {
+    ///
+    /// Returns an `SmtpMailerError::DkimSign` error if the message cannot be DKIM-signed (crate feature `dkim` only).
+    ///
+    /// Returns an `SmtpMailerError::MissingDkimSigner` error if no DKIM signer is aligned with the message's `From:` domain,
+    /// and DKIM signatures are required (crate feature `dkim` only).
+    #[cfg_attr(feature = "tracing", instrument(skip(self, message)))]
+    #[cfg_attr(not(feature = "dkim"), allow(unused_mut))]
+    pub async fn send_mail_with_options(
+        &self,
+        mut message: Message<'_>,
+        options: &SmtpSendOptions,
+    ) -> Result<SmtpSendReport, SmtpMailerError> {
         #[cfg(feature = "tracing")]
         // Extract recipient addresses for tracing log output.
         let recipient_addresses = util::format_recipient_addresses(&message);
 
+        #[cfg(feature = "tracing")]
         info!("Sending SMTP mail to {recipient_addresses}...");
 
-        let connection = self.inner.connect().await;
+        #[cfg(feature = "dkim")]
+        if !self.dkim_signers.is_empty() {
+            match dkim::sign(&self.dkim_signers, &message.body) {
+                Ok(Some(signed_body)) => message.body = signed_body.into(),
+                Ok(None) if self.dkim_require_signature => {
+                    #[cfg(feature = "tracing")]
+                    error!(
+                        "No DKIM signer for the From domain of SMTP mail to {recipient_addresses}"
+                    );
 
-        #[cfg(feature = "tracing")]
-        match &connection {
-            Ok(_) => {}
-            Err(error) => error!(
-                ?error,
-                "Failed to connect to SMTP host for mail to {recipient_addresses}"
-            ),
+                    return Err(SmtpMailerError::MissingDkimSigner);
+                }
+                Ok(None) => {
+                    #[cfg(feature = "tracing")]
+                    warn!(
+                        "No DKIM signer for the From domain of SMTP mail to {recipient_addresses}; sending unsigned"
+                    );
+                }
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    error!(
+                        ?error,
+                        "Failed to DKIM-sign SMTP mail to {recipient_addresses}"
+                    );
+
+                    return Err(SmtpMailerError::DkimSign(error));
+                }
+            }
+        }
+
+        // Wait for a free session slot, then reuse an idle session, or connect a new one.
+        let _permit = self.pool.permit().await;
+
+        let (mut session, reused) = match self.pool.checkout().await {
+            Some(session) => (session, true),
+            None => (self.connect_pooled().await?, false),
+        };
+
+        let mut response = delivery::send(&mut session, &message, options).await;
+
+        // The server may have dropped a reused session after its health check.
+        // Retry once on a new connection, unless the message data may have been sent already.
+        if reused
+            && response
+                .as_ref()
+                .is_err_and(DeliveryError::is_session_lost_before_data)
+        {
+            #[cfg(feature = "tracing")]
+            debug!("Pooled SMTP session was dropped; retrying on a new connection...");
+
+            session = self.connect_pooled().await?;
+            response = delivery::send(&mut session, &message, options).await;
         }
 
-        let response = connection
-            .map_err(SmtpMailerError::Connect)?
-            .send(message)
-            .await;
+        let response = response.map_err(|delivery_error| delivery_error.error);
+
+        match &response {
+            Ok(_) => self.pool.checkin(session, false).await,
+            // The session is unusable and is dropped.
+            Err(SmtpMailerError::Send(error)) if pool::is_connection_error(error) => {}
+            // The server rejected the mail transaction; the session can be reused after a reset.
+            Err(_) => self.pool.checkin(session, true).await,
+        }
 
         #[cfg(feature = "tracing")]
         match &response {
-            Ok(_) => {
+            Ok(report) => {
                 info!("Sent SMTP mail to {recipient_addresses}");
+
+                for (address, reply) in report.rejected() {
+                    warn!(%address, %reply, "SMTP host rejected recipient");
+                }
             }
             Err(error) => {
                 error!(?error, "Failed to send SMTP mail to {recipient_addresses}");
             }
         }
 
-        Ok(response.map_err(SmtpMailerError::Send)?)
+        response
+    }
+}
+
+/// Builder for an [`SmtpMailer`], created by [`SmtpMailer::builder`].
+///
+/// Defaults to implicit TLS, a 30 second timeout, no authentication and denying invalid certificates.
+#[derive(Clone, Debug)]
+pub struct SmtpMailerBuilder {
+    host: String,
+    port: u16,
+    timeout: Duration,
+    credentials: Option<(String, SecretString)>,
+    oauth2: Option<(String, Arc<dyn SmtpTokenSource>)>,
+    helo_host: Option<String>,
+    local_ip: Option<IpAddr>,
+    tls_mode: SmtpTlsMode,
+    auth_mechanisms: Vec<SmtpAuthMechanism>,
+    invalid_certs: SmtpInvalidCertsPolicy,
+    tls_config: TlsConfig,
+    #[cfg(feature = "dkim")]
+    dkim_signers: Vec<Arc<SmtpDkimSigner>>,
+    #[cfg(feature = "dkim")]
+    dkim_require_signature: bool,
+    pool_config: SmtpPoolConfig,
+}
+
+impl SmtpMailerBuilder {
+    /// Create a new builder for an SMTP mailer client connecting to `host` on `port`.
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            timeout: DEFAULT_TIMEOUT,
+            credentials: None,
+            oauth2: None,
+            helo_host: None,
+            local_ip: None,
+            tls_mode: SmtpTlsMode::default(),
+            auth_mechanisms: Vec::new(),
+            invalid_certs: SmtpInvalidCertsPolicy::default(),
+            tls_config: TlsConfig::default(),
+            #[cfg(feature = "dkim")]
+            dkim_signers: Vec::new(),
+            #[cfg(feature = "dkim")]
+            dkim_require_signature: false,
+            pool_config: SmtpPoolConfig::default(),
+        }
+    }
+
+    /// Set the timeout for establishing an SMTP session, and for each SMTP command.
+    ///
+    /// Default: 30 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Authenticate with username and password.
+    ///
+    /// Without credentials, no authentication is attempted, e.g. for unauthenticated relays.
+    ///
+    /// Replaces [`SmtpMailerBuilder::oauth2`].
+    pub fn credentials(mut self, user: impl Into<String>, password: SecretString) -> Self {
+        self.credentials = Some((user.into(), password));
+        self.oauth2 = None;
+        self
+    }
+
+    /// Authenticate as `user` with `XOAUTH2` or `OAUTHBEARER`, using access tokens retrieved from `token_source`.
+    ///
+    /// The access token is cached, and refreshed once the server rejects it with `535`.
+    ///
+    /// Replaces [`SmtpMailerBuilder::credentials`].
+    pub fn oauth2(
+        mut self,
+        user: impl Into<String>,
+        token_source: impl SmtpTokenSource + 'static,
+    ) -> Self {
+        self.oauth2 = Some((user.into(), Arc::new(token_source)));
+        self.credentials = None;
+        self
+    }
+
+    /// Set the domain sent with `EHLO`.
+    ///
+    /// Many relays reject clients whose `EHLO` domain does not match the reverse DNS name
+    /// of the client's IP address. Set this to the fully qualified domain name of the sending host,
+    /// or of the [`SmtpMailerBuilder::local_ip`], if configured.
+    ///
+    /// Default: the local machine's hostname.
+    pub fn helo_host(mut self, helo_host: impl Into<String>) -> Self {
+        self.helo_host = Some(helo_host.into());
+        self
+    }
+
+    /// Bind to the given local IP address before connecting, e.g. to send from a specific source IP address
+    /// on a multi-homed host. The local port is chosen by the operating system.
+    ///
+    /// Only SMTP host addresses of the same address family (IPv4 or IPv6) as the local IP address are tried.
+    ///
+    /// Default: chosen by the operating system.
+    pub fn local_ip(mut self, local_ip: impl Into<IpAddr>) -> Self {
+        self.local_ip = Some(local_ip.into());
+        self
+    }
+
+    /// Select how the SMTP connection is secured.
+    ///
+    /// Default: [`SmtpTlsMode::Implicit`].
+    pub fn tls_mode(mut self, tls_mode: SmtpTlsMode) -> Self {
+        self.tls_mode = tls_mode;
+        self
+    }
+
+    /// Restrict the authentication mechanisms to try, in order of preference.
+    ///
+    /// Default: the most secure mechanism supported by both client and server.
+    pub fn auth_mechanisms(
+        mut self,
+        auth_mechanisms: impl IntoIterator<Item = SmtpAuthMechanism>,
+    ) -> Self {
+        self.auth_mechanisms = auth_mechanisms.into_iter().collect();
+        self
+    }
+
+    /// Allow or deny invalid TLS certificates.
+    ///
+    /// **Never use [`SmtpInvalidCertsPolicy::Allow`] in production!**
+    ///
+    /// Default: [`SmtpInvalidCertsPolicy::Deny`].
+    pub fn invalid_certs(mut self, invalid_certs: SmtpInvalidCertsPolicy) -> Self {
+        self.invalid_certs = invalid_certs;
+        self
+    }
+
+    /// Trust an additional root certificate, e.g. of a private certificate authority,
+    /// in addition to the platform's trusted root certificates.
+    pub fn root_certificate(mut self, root_certificate: SmtpCertificate) -> Self {
+        self.tls_config.root_certificates.push(root_certificate);
+        self
+    }
+
+    /// Pin the server's public key by the SHA-256 hash of its DER-encoded subject public key info (SPKI).
+    ///
+    /// If any pins are configured, the connection is only established if the server's certificate,
+    /// or an intermediate certificate in its chain, matches one of the pins.
+    /// The certificate chain is still verified, unless invalid certificates are allowed.
+    ///
+    /// The hex-encoded hash of a PEM-encoded certificate's public key can be computed with:
+    ///
+    /// ```sh
+    /// openssl x509 -in cert.pem -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256
+    /// ```
+    pub fn pin_spki_sha256(mut self, spki_sha256: [u8; 32]) -> Self {
+        self.tls_config.spki_pins.push(spki_sha256);
+        self
+    }
+
+    /// Present a client certificate to SMTP relays requiring mutual TLS.
+    pub fn client_certificate(mut self, client_certificate: SmtpClientCertificate) -> Self {
+        self.tls_config.client_certificate = Some(client_certificate);
+        self
+    }
+
+    /// Add a DKIM signer. May be called multiple times, e.g. for several domains,
+    /// or to dual-sign with RSA and Ed25519 keys.
+    ///
+    /// Each message is signed with the signers for its `From:` domain, or the closest parent domain.
+    /// Messages without a matching signer are sent unsigned, logging a warning with the `tracing` feature,
+    /// unless [`SmtpMailerBuilder::dkim_require_signature`] is enabled.
+    #[cfg(feature = "dkim")]
+    pub fn dkim_signer(mut self, dkim_signer: SmtpDkimSigner) -> Self {
+        self.dkim_signers.push(Arc::new(dkim_signer));
+        self
+    }
+
+    /// Set whether messages without a DKIM signer for their `From:` domain are rejected
+    /// with an [`SmtpMailerError::MissingDkimSigner`] error, rather than sent unsigned.
+    ///
+    /// Only applies if at least one DKIM signer is configured with [`SmtpMailerBuilder::dkim_signer`].
+    ///
+    /// Default: `false`.
+    #[cfg(feature = "dkim")]
+    pub fn dkim_require_signature(mut self, dkim_require_signature: bool) -> Self {
+        self.dkim_require_signature = dkim_require_signature;
+        self
+    }
+
+    /// Configure the pool of reused SMTP sessions.
+    ///
+    /// Default: [`SmtpPoolConfig::default`].
+    pub fn pool_config(mut self, pool_config: SmtpPoolConfig) -> Self {
+        self.pool_config = pool_config;
+        self
+    }
+
+    /// Build the SMTP mailer client.
+    ///
+    /// No connection is established until the first message is sent.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`SmtpMailerError::Build`] error
+    /// if the SMTP client cannot be built,
+    /// e.g. if the client certificate does not match its private key.
+    #[cfg_attr(feature = "tracing", instrument)]
+    pub fn build(self) -> Result<SmtpMailer, SmtpMailerError> {
+        let mut smtp_client = SmtpClientBuilder::new(self.host, self.port)
+            .map_err(SmtpMailerError::Build)?
+            .timeout(self.timeout);
+
+        if let Some((user, password)) = self.credentials {
+            smtp_client = smtp_client.credentials((user, password.expose_secret().into()));
+        }
+
+        if let Some(helo_host) = self.helo_host {
+            smtp_client = smtp_client.helo_host(helo_host);
+        }
+
+        if let Some(local_ip) = self.local_ip {
+            smtp_client = smtp_client.local_ip(local_ip);
+        }
+
+        if self.tls_config.is_custom() {
+            smtp_client.tls_connector = self
+                .tls_config
+                .build_connector(&self.invalid_certs)
+                .map_err(SmtpMailerError::Build)?;
+        } else if matches!(self.invalid_certs, SmtpInvalidCertsPolicy::Allow) {
+            smtp_client = smtp_client.allow_invalid_certs();
+        }
+
+        Ok(SmtpMailer {
+            inner: smtp_client,
+            tls_mode: self.tls_mode,
+            auth_mechanisms: self.auth_mechanisms,
+            oauth2: self.oauth2.map(|(user, token_source)| {
+                Arc::new(OAuth2Authentication::new(user, token_source))
+            }),
+            #[cfg(feature = "dkim")]
+            dkim_signers: self.dkim_signers,
+            #[cfg(feature = "dkim")]
+            dkim_require_signature: self.dkim_require_signature,
+            pool: Arc::new(SmtpPool::new(self.pool_config)),
+        })
+    }
+}
+
+// == Mailer ==
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    type Error = SmtpMailerError;
+
+    /// Send the prepared MIME message via an SMTP connection, using the previously configured credentials.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpMailerError::Connect`] error if a connection to the SMTP server cannot be established.
+    ///
+    /// Returns an [`SmtpMailerError::MissingStartTls`] error if STARTTLS is required but not offered by the server.
+    ///
+    /// Returns an [`SmtpMailerError::AccessToken`] error if an OAuth2 access token cannot be retrieved.
+    ///
+    /// Returns an [`SmtpMailerError::MessageTooLarge`] error if the message exceeds the server's maximum message size.
+    ///
+    /// Returns an [`SmtpMailerError::MissingSmtpUtf8`] error if the message requires `SMTPUTF8`,
+    /// but the server does not offer it.
+    ///
+    /// Returns an [`SmtpMailerError::RecipientsRejected`] error if the server rejected recipients,
+    /// and the message was therefore not sent.
+    ///
+    /// Returns an [`SmtpMailerError::Send`] error if the connection was established but sending the e-mail message failed.
+    ///
+    /// Returns an `SmtpMailerError::DkimSign` error if the message cannot be DKIM-signed (crate feature `dkim` only).
+    ///
+    /// Returns an `SmtpMailerError::MissingDkimSigner` error if no DKIM signer is aligned with the message's `From:` domain,
+    /// and DKIM signatures are required (crate feature `dkim` only).
+    async fn send_mail(&self, message: Message<'_>) -> Result<(), Self::Error> {
+        self.send_mail_with_options(message, &SmtpSendOptions::default())
+            .await?;
+
+        Ok(())
     }
 }
 
@@ -316,7 +1035,24 @@ impl DynMailer for SmtpMailer {
     ///
     /// Returns a boxed, type-erased [`SmtpMailerError::Connect`] error if a connection to the SMTP server cannot be established.
     ///
+    /// Returns a boxed, type-erased [`SmtpMailerError::MissingStartTls`] error if STARTTLS is required but not offered by the server.
+    ///
+    /// Returns a boxed, type-erased [`SmtpMailerError::AccessToken`] error if an OAuth2 access token cannot be retrieved.
+    ///
+    /// Returns a boxed, type-erased [`SmtpMailerError::MessageTooLarge`] error if the message exceeds the server's maximum message size.
+    ///
+    /// Returns a boxed, type-erased [`SmtpMailerError::MissingSmtpUtf8`] error if the message requires `SMTPUTF8`,
+    /// but the server does not offer it.
+    ///
+    /// Returns a boxed, type-erased [`SmtpMailerError::RecipientsRejected`] error if the server rejected recipients,
+    /// and the message was therefore not sent.
+    ///
     /// Returns a boxed, type-erased [`SmtpMailerError::Send`] error if the connection was established but sending the e-mail message failed.
+    ///
+    /// Returns a boxed, type-erased `SmtpMailerError::DkimSign` error if the message cannot be DKIM-signed (crate feature `dkim` only).
+    ///
+    /// Returns a boxed, type-erased `SmtpMailerError::MissingDkimSigner` error if no DKIM signer is aligned
+    /// with the message's `From:` domain, and DKIM signatures are required (crate feature `dkim` only).
     #[cfg_attr(feature = "tracing", instrument(skip(message)))]
     async fn send_mail(&self, message: Message<'_>) -> Result<(), DynMailerError> {
         Mailer::send_mail(self, message).await.map_err(Into::into)
@@ -0,0 +1,142 @@
+//! OAuth2 access token sources for `XOAUTH2` and `OAUTHBEARER` authentication of the [`SmtpMailer`](crate::SmtpMailer).
+
+use std::future::Future;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use secrecy::{ExposeSecret, SecretString};
+use tokio::sync::Mutex;
+
+use async_mailer_core::mail_send::Credentials;
+
+use crate::SmtpAuthMechanism;
+
+/// Type-erased error returned by an [`SmtpTokenSource`].
+pub type SmtpTokenSourceError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// Pluggable source of OAuth2 access tokens, used by the [`SmtpMailer`](crate::SmtpMailer)
+/// to authenticate with `XOAUTH2` or `OAUTHBEARER`,
+/// e.g. against the Microsoft 365 or Gmail SMTP servers.
+///
+/// The [`SmtpMailer`](crate::SmtpMailer) caches the returned access token,
+/// and only retrieves a new token once the server rejects the cached token with `535`.
+///
+/// Implemented by [`FnSmtpTokenSource`], wrapping an arbitrary async closure.
+#[async_trait]
+pub trait SmtpTokenSource: std::fmt::Debug + Send + Sync {
+    /// Retrieve a new access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpTokenSourceError`] if the access token cannot be retrieved.
+    async fn access_token(&self) -> Result<SecretString, SmtpTokenSourceError>;
+}
+
+/// [`SmtpTokenSource`] wrapping an arbitrary async closure.
+///
+/// The closure is called whenever a new access token is required.
+#[derive(Clone)]
+pub struct FnSmtpTokenSource<F> {
+    closure: F,
+}
+
+impl<F, Fut, E> FnSmtpTokenSource<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<SecretString, E>> + Send,
+    E: Into<SmtpTokenSourceError>,
+{
+    /// Create a new token source from an async closure returning an access token.
+    pub fn new(closure: F) -> Self {
+        Self { closure }
+    }
+}
+
+impl<F> std::fmt::Debug for FnSmtpTokenSource<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FnSmtpTokenSource").finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl<F, Fut, E> SmtpTokenSource for FnSmtpTokenSource<F>
+where
+    F: Fn() -> Fut + Send + Sync,
+    Fut: Future<Output = Result<SecretString, E>> + Send,
+    E: Into<SmtpTokenSourceError>,
+{
+    /// Call the closure to retrieve a new access token.
+    ///
+    /// # Errors
+    ///
+    /// Returns the closure's error, converted into an [`SmtpTokenSourceError`].
+    async fn access_token(&self) -> Result<SecretString, SmtpTokenSourceError> {
+        (self.closure)().await.map_err(Into::into)
+    }
+}
+
+/// OAuth2 authentication of a user, caching the access token retrieved from the token source.
+///
+/// Shared by all clones of an [`SmtpMailer`](crate::SmtpMailer).
+#[derive(Debug)]
+pub(crate) struct OAuth2Authentication {
+    user: String,
+    token_source: Arc<dyn SmtpTokenSource>,
+    access_token: Mutex<Option<SecretString>>,
+}
+
+impl OAuth2Authentication {
+    pub(crate) fn new(user: String, token_source: Arc<dyn SmtpTokenSource>) -> Self {
+        Self {
+            user,
+            token_source,
+            access_token: Mutex::new(None),
+        }
+    }
+
+    /// Return the cached access token, or retrieve a new one if none is cached or `refresh` is set.
+    ///
+    /// Concurrent callers share a single in-flight token request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpTokenSourceError`] if the token source fails.
+    pub(crate) async fn access_token(
+        &self,
+        refresh: bool,
+    ) -> Result<SecretString, SmtpTokenSourceError> {
+        let mut access_token = self.access_token.lock().await;
+
+        if let (Some(access_token), false) = (access_token.as_ref(), refresh) {
+            return Ok(access_token.clone());
+        }
+
+        let new_access_token = self.token_source.access_token().await?;
+        *access_token = Some(new_access_token.clone());
+
+        Ok(new_access_token)
+    }
+
+    /// Build the `mail-send` credentials for the OAuth2 `mechanism`.
+    ///
+    /// Returns `None` for password-based mechanisms.
+    pub(crate) fn credentials(
+        &self,
+        mechanism: SmtpAuthMechanism,
+        access_token: &SecretString,
+    ) -> Option<Credentials<String>> {
+        match mechanism {
+            SmtpAuthMechanism::XOAuth2 => Some(Credentials::new_xoauth2(
+                self.user.clone(),
+                access_token.expose_secret().to_string(),
+            )),
+            // RFC 7628 GS2 header, followed by the bearer token.
+            SmtpAuthMechanism::OAuthBearer => Some(Credentials::new_oauth(format!(
+                "n,a={user},\x01auth=Bearer {token}\x01\x01",
+                user = self.user,
+                token = access_token.expose_secret(),
+            ))),
+            SmtpAuthMechanism::Plain | SmtpAuthMechanism::Login => None,
+        }
+    }
+}
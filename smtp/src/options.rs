@@ -0,0 +1,176 @@
+//! Per-message send options of the [`SmtpMailer`](crate::SmtpMailer).
+
+/// Condition under which a delivery status notification (DSN, RFC 3461) is requested for a recipient,
+/// passed to [`SmtpSendOptions::dsn_notify`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpDsnNotify {
+    /// Never send a delivery status notification, not even on failure.
+    ///
+    /// Takes precedence over all other conditions.
+    Never,
+
+    /// Notify on successful delivery.
+    Success,
+
+    /// Notify on delivery failure.
+    Failure,
+
+    /// Notify if delivery is delayed.
+    Delay,
+}
+
+impl SmtpDsnNotify {
+    /// The `NOTIFY` parameter keyword.
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Never => "NEVER",
+            Self::Success => "SUCCESS",
+            Self::Failure => "FAILURE",
+            Self::Delay => "DELAY",
+        }
+    }
+}
+
+/// How much of the message is returned with a failure delivery status notification (DSN, RFC 3461),
+/// passed to [`SmtpSendOptions::dsn_return`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmtpDsnReturn {
+    /// Return the full message (`RET=FULL`).
+    Full,
+
+    /// Return the message headers only (`RET=HDRS`).
+    Headers,
+}
+
+impl SmtpDsnReturn {
+    /// The `RET` parameter value.
+    pub(crate) fn as_str(self) -> &'static str {
+        match self {
+            Self::Full => "FULL",
+            Self::Headers => "HDRS",
+        }
+    }
+}
+
+/// Per-message options, passed to [`SmtpMailer::send_mail_with_options`](crate::SmtpMailer::send_mail_with_options).
+#[derive(Clone, Debug, Default)]
+pub struct SmtpSendOptions {
+    pub(crate) partial_delivery: bool,
+    pub(crate) dsn_notify: Option<Vec<SmtpDsnNotify>>,
+    pub(crate) dsn_return: Option<SmtpDsnReturn>,
+    pub(crate) dsn_envelope_id: Option<String>,
+}
+
+impl SmtpSendOptions {
+    /// Create new send options with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set whether the message is sent to the accepted recipients if the server rejects some of the recipients.
+    ///
+    /// If disabled, the message is not sent if any recipient is rejected,
+    /// and an [`SmtpMailerError::RecipientsRejected`](crate::SmtpMailerError::RecipientsRejected) error is returned.
+    /// The message is never sent if all recipients are rejected.
+    ///
+    /// Default: `false`.
+    pub fn partial_delivery(mut self, partial_delivery: bool) -> Self {
+        self.partial_delivery = partial_delivery;
+        self
+    }
+
+    /// Request delivery status notifications for all recipients under the given conditions (DSN `NOTIFY`).
+    ///
+    /// Default: the server's default, usually notifying on failure.
+    pub fn dsn_notify(mut self, dsn_notify: impl IntoIterator<Item = SmtpDsnNotify>) -> Self {
+        self.dsn_notify = Some(dsn_notify.into_iter().collect());
+        self
+    }
+
+    /// Set how much of the message is returned with a failure delivery status notification (DSN `RET`).
+    ///
+    /// Default: the server's default.
+    pub fn dsn_return(mut self, dsn_return: SmtpDsnReturn) -> Self {
+        self.dsn_return = Some(dsn_return);
+        self
+    }
+
+    /// Set the envelope identifier, returned with delivery status notifications
+    /// to correlate them with the sent message (DSN `ENVID`).
+    pub fn dsn_envelope_id(mut self, dsn_envelope_id: impl Into<String>) -> Self {
+        self.dsn_envelope_id = Some(dsn_envelope_id.into());
+        self
+    }
+
+    /// Whether any delivery status notification parameters are set.
+    pub(crate) fn requests_dsn(&self) -> bool {
+        self.dsn_notify.is_some() || self.dsn_return.is_some() || self.dsn_envelope_id.is_some()
+    }
+
+    /// The `NOTIFY` parameter value, if set.
+    ///
+    /// `NEVER` takes precedence over all other conditions. An empty list of conditions is sent as `NEVER`.
+    pub(crate) fn dsn_notify_value(&self) -> Option<String> {
+        let dsn_notify = self.dsn_notify.as_ref()?;
+
+        if dsn_notify.is_empty() || dsn_notify.contains(&SmtpDsnNotify::Never) {
+            return Some(SmtpDsnNotify::Never.as_str().to_string());
+        }
+
+        let mut conditions: Vec<&str> = Vec::new();
+        for condition in dsn_notify {
+            if !conditions.contains(&condition.as_str()) {
+                conditions.push(condition.as_str());
+            }
+        }
+
+        Some(conditions.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requests_dsn_if_any_parameter_is_set() {
+        assert!(!SmtpSendOptions::new().requests_dsn());
+        assert!(!SmtpSendOptions::new().partial_delivery(true).requests_dsn());
+        assert!(SmtpSendOptions::new().dsn_notify([]).requests_dsn());
+        assert!(SmtpSendOptions::new()
+            .dsn_return(SmtpDsnReturn::Headers)
+            .requests_dsn());
+        assert!(SmtpSendOptions::new()
+            .dsn_envelope_id("order-1234")
+            .requests_dsn());
+    }
+
+    #[test]
+    fn encodes_dsn_notify_value() {
+        let dsn_notify_value = |dsn_notify: &[SmtpDsnNotify]| {
+            SmtpSendOptions::new()
+                .dsn_notify(dsn_notify.iter().copied())
+                .dsn_notify_value()
+        };
+
+        assert_eq!(SmtpSendOptions::new().dsn_notify_value(), None);
+        assert_eq!(dsn_notify_value(&[]).as_deref(), Some("NEVER"));
+        assert_eq!(
+            dsn_notify_value(&[SmtpDsnNotify::Failure, SmtpDsnNotify::Delay]).as_deref(),
+            Some("FAILURE,DELAY")
+        );
+        assert_eq!(
+            dsn_notify_value(&[
+                SmtpDsnNotify::Success,
+                SmtpDsnNotify::Failure,
+                SmtpDsnNotify::Success
+            ])
+            .as_deref(),
+            Some("SUCCESS,FAILURE")
+        );
+        assert_eq!(
+            dsn_notify_value(&[SmtpDsnNotify::Failure, SmtpDsnNotify::Never]).as_deref(),
+            Some("NEVER")
+        );
+    }
+}
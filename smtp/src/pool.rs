@@ -0,0 +1,523 @@
+//! Pooling of authenticated SMTP sessions of the [`SmtpMailer`](crate::SmtpMailer).
+//!
+//! Establishing an SMTP session requires a TCP connection, a TLS handshake, `EHLO` and `AUTH`.
+//! Sessions are therefore kept open after sending a message, and reused for subsequent messages.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+#[cfg(feature = "tracing")]
+use tracing::debug;
+
+use async_mailer_core::mail_send::{self, SmtpClient};
+use smtp_proto::EhloResponse;
+
+/// A connected SMTP stream, either in plaintext or TLS-encrypted.
+pub(crate) trait SmtpStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> SmtpStream for T {}
+
+/// An established, authenticated SMTP session.
+pub(crate) type SmtpSession = SmtpClient<Box<dyn SmtpStream>>;
+
+/// Connection pool configuration of the [`SmtpMailer`](crate::SmtpMailer).
+///
+/// Idle sessions are checked for expiry and health when taken out of the pool.
+/// Sessions which were idle for longer than the idle timeout, or which do not respond to `NOOP`,
+/// are discarded and replaced by a new connection.
+#[derive(Clone, Debug)]
+pub struct SmtpPoolConfig {
+    pub(crate) max_size: usize,
+    pub(crate) idle_timeout: Duration,
+    pub(crate) max_messages_per_connection: usize,
+}
+
+impl Default for SmtpPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_size: 4,
+            idle_timeout: Duration::from_secs(60),
+            max_messages_per_connection: 100,
+        }
+    }
+}
+
+impl SmtpPoolConfig {
+    /// Create a new connection pool configuration with default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the maximum number of concurrently open SMTP sessions.
+    /// Further messages wait until a session is returned to the pool.
+    ///
+    /// Default: 4.
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    /// Set how long an SMTP session may be idle before it is discarded rather than reused.
+    ///
+    /// Keep this below the server's idle timeout, which is at least 5 minutes per RFC 5321.
+    ///
+    /// Default: 60 seconds.
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Set the maximum number of messages sent through a single SMTP session before it is closed.
+    /// A value of 1 disables session reuse.
+    ///
+    /// Default: 100.
+    pub fn max_messages_per_connection(mut self, max_messages_per_connection: usize) -> Self {
+        self.max_messages_per_connection = max_messages_per_connection.max(1);
+        self
+    }
+}
+
+/// A pool of idle SMTP sessions, shared by all clones of an [`SmtpMailer`](crate::SmtpMailer).
+pub(crate) struct SmtpPool {
+    config: SmtpPoolConfig,
+    permits: Semaphore,
+    idle: Mutex<Vec<PooledSession>>,
+}
+
+/// An SMTP session, checked out of the pool or newly connected.
+pub(crate) struct PooledSession {
+    pub(crate) client: SmtpSession,
+    /// The server's `EHLO` capabilities, as advertised on the secured session.
+    pub(crate) capabilities: EhloResponse<String>,
+    messages_sent: usize,
+    idle_since: Instant,
+}
+
+impl PooledSession {
+    /// Wrap a newly connected SMTP session and its `EHLO` capabilities.
+    pub(crate) fn new(client: SmtpSession, capabilities: EhloResponse<String>) -> Self {
+        Self {
+            client,
+            capabilities,
+            messages_sent: 0,
+            idle_since: Instant::now(),
+        }
+    }
+}
+
+impl SmtpPool {
+    /// Create a new, empty pool.
+    pub(crate) fn new(config: SmtpPoolConfig) -> Self {
+        Self {
+            permits: Semaphore::new(config.max_size),
+            idle: Mutex::new(Vec::new()),
+            config,
+        }
+    }
+
+    /// Wait until fewer than the maximum number of sessions are in use.
+    ///
+    /// The session slot is released when the returned permit is dropped.
+    pub(crate) async fn permit(&self) -> Option<SemaphorePermit<'_>> {
+        // The semaphore is never closed.
+        self.permits.acquire().await.ok()
+    }
+
+    /// Take the most recently used idle session out of the pool.
+    ///
+    /// Sessions which were idle for too long, or fail the `NOOP` health check, are discarded.
+    /// Returns `None` if no healthy idle session is available, so a new connection must be established.
+    pub(crate) async fn checkout(&self) -> Option<PooledSession> {
+        loop {
+            let mut session = self.idle.lock().ok()?.pop()?;
+
+            if session.idle_since.elapsed() > self.config.idle_timeout {
+                #[cfg(feature = "tracing")]
+                debug!("Discarding expired idle SMTP session");
+
+                continue;
+            }
+
+            match session.client.noop().await {
+                Ok(()) => return Some(session),
+                #[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+                Err(error) => {
+                    #[cfg(feature = "tracing")]
+                    debug!(?error, "Discarding SMTP session failing the health check");
+                }
+            }
+        }
+    }
+
+    /// Return a session to the pool after a mail transaction.
+    ///
+    /// If the transaction was rejected by the server, the session is reset with `RSET` first.
+    /// Sessions which reached the maximum number of messages per connection are closed with `QUIT` instead.
+    pub(crate) async fn checkin(&self, mut session: PooledSession, reset: bool) {
+        session.messages_sent += 1;
+
+        if session.messages_sent >= self.config.max_messages_per_connection {
+            let _quit = session.client.quit().await;
+
+            #[cfg(feature = "tracing")]
+            debug!(result = ?_quit, "Closed SMTP session after reaching the maximum number of messages");
+
+            return;
+        }
+
+        if reset {
+            if let Err(_error) = session.client.rset().await {
+                #[cfg(feature = "tracing")]
+                debug!(error = ?_error, "Discarding SMTP session failing to reset");
+
+                return;
+            }
+        }
+
+        session.idle_since = Instant::now();
+
+        if let Ok(mut idle) = self.idle.lock() {
+            idle.push(session);
+        }
+    }
+}
+
+/// Whether the error indicates that the SMTP session is unusable,
+/// rather than the server rejecting the mail transaction.
+pub(crate) fn is_connection_error(error: &mail_send::Error) -> bool {
+    match error {
+        mail_send::Error::Io(_)
+        | mail_send::Error::Tls(_)
+        | mail_send::Error::Timeout
+        | mail_send::Error::UnparseableReply => true,
+        // 421: Service not available, closing transmission channel.
+        mail_send::Error::UnexpectedReply(response) => response.code == 421,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+
+    use crate::{SmtpMailer, SmtpMailerError, SmtpSendOptions, SmtpTlsMode};
+
+    use super::*;
+
+    /// Behavior of the [`StubSmtpServer`].
+    #[derive(Clone, Copy, Default)]
+    struct StubBehavior {
+        /// Reject all recipients with `550`.
+        reject_recipients: bool,
+
+        /// Answer `NOOP` with `421` and close the connection.
+        fail_noop: bool,
+
+        /// Answer `NOOP` with `250`, then close the connection, as if the server dropped the idle session.
+        close_after_noop: bool,
+
+        /// Delay before accepting the message data.
+        data_delay: Duration,
+    }
+
+    /// Plaintext stub SMTP server, recording the commands received on each connection.
+    struct StubSmtpServer {
+        port: u16,
+        commands: Arc<Mutex<Vec<String>>>,
+        connections: Arc<AtomicUsize>,
+        max_concurrent_transactions: Arc<AtomicUsize>,
+    }
+
+    impl StubSmtpServer {
+        async fn start(behavior: StubBehavior) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            let commands = Arc::new(Mutex::new(Vec::new()));
+            let connections = Arc::new(AtomicUsize::new(0));
+            let transactions = Arc::new(AtomicUsize::new(0));
+            let max_concurrent_transactions = Arc::new(AtomicUsize::new(0));
+
+            tokio::spawn({
+                let commands = commands.clone();
+                let connections = connections.clone();
+                let max_concurrent_transactions = max_concurrent_transactions.clone();
+
+                async move {
+                    loop {
+                        let (stream, _) = listener.accept().await.unwrap();
+                        let connection = connections.fetch_add(1, Ordering::SeqCst) + 1;
+                        let commands = commands.clone();
+                        let transactions = transactions.clone();
+                        let max_concurrent_transactions = max_concurrent_transactions.clone();
+
+                        tokio::spawn(async move {
+                            let mut stream = BufReader::new(stream);
+                            stream
+                                .get_mut()
+                                .write_all(b"220 stub ESMTP\r\n")
+                                .await
+                                .unwrap();
+
+                            loop {
+                                let mut line = String::new();
+                                if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                                    return;
+                                }
+
+                                let command = line
+                                    .split([' ', ':', '\r'])
+                                    .next()
+                                    .unwrap_or_default()
+                                    .to_ascii_uppercase();
+                                commands
+                                    .lock()
+                                    .unwrap()
+                                    .push(format!("{connection} {command}"));
+
+                                let reply: &[u8] = match command.as_str() {
+                                    "EHLO" => b"250 stub\r\n",
+                                    "MAIL" => {
+                                        let concurrent =
+                                            transactions.fetch_add(1, Ordering::SeqCst) + 1;
+                                        max_concurrent_transactions
+                                            .fetch_max(concurrent, Ordering::SeqCst);
+                                        b"250 2.1.0 OK\r\n"
+                                    }
+                                    "RCPT" if behavior.reject_recipients => {
+                                        transactions.fetch_sub(1, Ordering::SeqCst);
+                                        b"550 5.1.1 mailbox unavailable\r\n"
+                                    }
+                                    "RCPT" => b"250 2.1.5 OK\r\n",
+                                    "DATA" => {
+                                        stream
+                                            .get_mut()
+                                            .write_all(b"354 go ahead\r\n")
+                                            .await
+                                            .unwrap();
+
+                                        loop {
+                                            let mut line = String::new();
+                                            stream.read_line(&mut line).await.unwrap();
+                                            if line == ".\r\n" {
+                                                break;
+                                            }
+                                        }
+
+                                        tokio::time::sleep(behavior.data_delay).await;
+                                        transactions.fetch_sub(1, Ordering::SeqCst);
+                                        b"250 2.0.0 queued\r\n"
+                                    }
+                                    "NOOP" if behavior.fail_noop => {
+                                        let _ = stream
+                                            .get_mut()
+                                            .write_all(b"421 4.4.2 closing\r\n")
+                                            .await;
+                                        return;
+                                    }
+                                    "NOOP" if behavior.close_after_noop => {
+                                        let _ =
+                                            stream.get_mut().write_all(b"250 2.0.0 OK\r\n").await;
+                                        return;
+                                    }
+                                    "NOOP" | "RSET" => b"250 2.0.0 OK\r\n",
+                                    "QUIT" => {
+                                        let _ =
+                                            stream.get_mut().write_all(b"221 2.0.0 bye\r\n").await;
+                                        return;
+                                    }
+                                    _ => b"500 5.5.1 unknown command\r\n",
+                                };
+
+                                if stream.get_mut().write_all(reply).await.is_err() {
+                                    return;
+                                }
+                            }
+                        });
+                    }
+                }
+            });
+
+            Self {
+                port,
+                commands,
+                connections,
+                max_concurrent_transactions,
+            }
+        }
+
+        fn mailer(&self, pool_config: SmtpPoolConfig) -> SmtpMailer {
+            SmtpMailer::builder("127.0.0.1", self.port)
+                .tls_mode(SmtpTlsMode::Plaintext)
+                .pool_config(pool_config)
+                .build()
+                .unwrap()
+        }
+
+        /// The commands received so far, each prefixed with the number of its connection.
+        fn commands(&self) -> Vec<String> {
+            self.commands.lock().unwrap().clone()
+        }
+
+        fn connections(&self) -> usize {
+            self.connections.load(Ordering::SeqCst)
+        }
+    }
+
+    async fn send(mailer: &SmtpMailer) -> Result<(), SmtpMailerError> {
+        let message = mail_send::smtp::message::Message::new(
+            "from@example.com",
+            ["to@example.com"],
+            b"Subject: Test\r\n\r\nHello.\r\n".as_slice(),
+        );
+
+        mailer
+            .send_mail_with_options(message, &SmtpSendOptions::default())
+            .await
+            .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn reuses_healthy_idle_session() {
+        let server = StubSmtpServer::start(StubBehavior::default()).await;
+        let mailer = server.mailer(SmtpPoolConfig::default());
+
+        send(&mailer).await.unwrap();
+        send(&mailer).await.unwrap();
+
+        assert_eq!(server.connections(), 1);
+        assert_eq!(
+            server.commands(),
+            ["1 EHLO", "1 MAIL", "1 RCPT", "1 DATA", "1 NOOP", "1 MAIL", "1 RCPT", "1 DATA"]
+        );
+    }
+
+    #[tokio::test]
+    async fn discards_expired_idle_session() {
+        let server = StubSmtpServer::start(StubBehavior::default()).await;
+        let mailer = server.mailer(SmtpPoolConfig::default().idle_timeout(Duration::ZERO));
+
+        send(&mailer).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        send(&mailer).await.unwrap();
+
+        assert_eq!(server.connections(), 2);
+        // The expired session is discarded without a health check.
+        assert!(!server.commands().contains(&"1 NOOP".to_string()));
+    }
+
+    #[tokio::test]
+    async fn discards_session_failing_health_check() {
+        let server = StubSmtpServer::start(StubBehavior {
+            fail_noop: true,
+            ..Default::default()
+        })
+        .await;
+        let mailer = server.mailer(SmtpPoolConfig::default());
+
+        send(&mailer).await.unwrap();
+        send(&mailer).await.unwrap();
+
+        assert_eq!(server.connections(), 2);
+        assert_eq!(
+            server.commands(),
+            [
+                "1 EHLO", "1 MAIL", "1 RCPT", "1 DATA", "1 NOOP", "2 EHLO", "2 MAIL", "2 RCPT",
+                "2 DATA"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn retries_on_new_connection_if_session_is_dropped_after_health_check() {
+        let server = StubSmtpServer::start(StubBehavior {
+            close_after_noop: true,
+            ..Default::default()
+        })
+        .await;
+        let mailer = server.mailer(SmtpPoolConfig::default());
+
+        send(&mailer).await.unwrap();
+        send(&mailer).await.unwrap();
+
+        assert_eq!(server.connections(), 2);
+        assert_eq!(
+            server.commands(),
+            [
+                "1 EHLO", "1 MAIL", "1 RCPT", "1 DATA", "1 NOOP", "2 EHLO", "2 MAIL", "2 RCPT",
+                "2 DATA"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn resets_session_after_rejected_transaction() {
+        let server = StubSmtpServer::start(StubBehavior {
+            reject_recipients: true,
+            ..Default::default()
+        })
+        .await;
+        let mailer = server.mailer(SmtpPoolConfig::default());
+
+        let error = send(&mailer).await.unwrap_err();
+        assert!(matches!(error, SmtpMailerError::RecipientsRejected(_)));
+        assert!(send(&mailer).await.is_err());
+
+        assert_eq!(server.connections(), 1);
+        assert_eq!(
+            server.commands(),
+            ["1 EHLO", "1 MAIL", "1 RCPT", "1 RSET", "1 NOOP", "1 MAIL", "1 RCPT", "1 RSET"]
+        );
+    }
+
+    #[tokio::test]
+    async fn closes_session_at_max_messages_per_connection() {
+        let server = StubSmtpServer::start(StubBehavior::default()).await;
+        let mailer = server.mailer(SmtpPoolConfig::default().max_messages_per_connection(2));
+
+        for _ in 0..3 {
+            send(&mailer).await.unwrap();
+        }
+
+        assert_eq!(server.connections(), 2);
+        assert_eq!(
+            server.commands(),
+            [
+                "1 EHLO", "1 MAIL", "1 RCPT", "1 DATA", "1 NOOP", "1 MAIL", "1 RCPT", "1 DATA",
+                "1 QUIT", "2 EHLO", "2 MAIL", "2 RCPT", "2 DATA"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn limits_concurrent_sessions() {
+        let server = StubSmtpServer::start(StubBehavior {
+            data_delay: Duration::from_millis(100),
+            ..Default::default()
+        })
+        .await;
+        let mailer = server.mailer(SmtpPoolConfig::default().max_size(1));
+
+        let (first, second) = tokio::join!(send(&mailer), send(&mailer));
+        first.unwrap();
+        second.unwrap();
+
+        // The second message waits for the session of the first one.
+        assert_eq!(server.connections(), 1);
+        assert_eq!(server.max_concurrent_transactions.load(Ordering::SeqCst), 1);
+
+        let mailer = server.mailer(SmtpPoolConfig::default().max_size(2));
+        let (first, second) = tokio::join!(send(&mailer), send(&mailer));
+        first.unwrap();
+        second.unwrap();
+
+        assert_eq!(server.connections(), 3);
+        assert_eq!(server.max_concurrent_transactions.load(Ordering::SeqCst), 2);
+    }
+}
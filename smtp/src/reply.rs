@@ -0,0 +1,167 @@
+//! SMTP server replies, as carried by [`SmtpMailerError`](crate::SmtpMailerError)s.
+
+use async_mailer_core::mail_send;
+
+/// An SMTP server reply rejecting a command, returned by [`SmtpMailerError::reply`](crate::SmtpMailerError::reply).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct SmtpReply {
+    /// Three-digit SMTP reply code (RFC 5321), e.g. `450` or `550`.
+    pub code: u16,
+
+    /// Enhanced mail system status code (RFC 3463), e.g. `5.1.1`, if sent by the server.
+    pub enhanced_status_code: Option<SmtpEnhancedStatusCode>,
+
+    /// Human-readable reply text sent by the server.
+    pub message: String,
+}
+
+impl SmtpReply {
+    /// Extract the server reply from a `mail-send` error, if the error was caused by a server reply.
+    pub(crate) fn from_error(error: &mail_send::Error) -> Option<Self> {
+        match error {
+            mail_send::Error::UnexpectedReply(response)
+            | mail_send::Error::AuthenticationFailed(response) => Some(Self {
+                code: response.code,
+                enhanced_status_code: SmtpEnhancedStatusCode::from_smtp_proto(response.esc),
+                message: response.message.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Whether the reply code is a transient negative completion reply (`4xx`),
+    /// i.e. the command may succeed if retried later, e.g. after greylisting.
+    pub fn is_transient(&self) -> bool {
+        (400..500).contains(&self.code)
+    }
+
+    /// Whether the reply code is a permanent negative completion reply (`5xx`),
+    /// i.e. the command will fail again if retried unchanged.
+    pub fn is_permanent(&self) -> bool {
+        (500..600).contains(&self.code)
+    }
+}
+
+impl std::fmt::Display for SmtpReply {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.enhanced_status_code {
+            Some(enhanced_status_code) => {
+                write!(f, "{} {enhanced_status_code} {}", self.code, self.message)
+            }
+            None => write!(f, "{} {}", self.code, self.message),
+        }
+    }
+}
+
+/// Enhanced mail system status code (RFC 3463) of an [`SmtpReply`], formatted as `class.subject.detail`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SmtpEnhancedStatusCode {
+    /// Class: `2` (success), `4` (persistent transient failure) or `5` (permanent failure).
+    pub class: u8,
+
+    /// Subject, e.g. `1` (addressing status) or `7` (security or policy status).
+    pub subject: u8,
+
+    /// Detail, e.g. `1` in `5.1.1` (bad destination mailbox address).
+    pub detail: u8,
+}
+
+impl SmtpEnhancedStatusCode {
+    /// Convert the `smtp-proto` representation, in which an all-zero code denotes its absence.
+    fn from_smtp_proto(esc: [u8; 3]) -> Option<Self> {
+        let [class, subject, detail] = esc;
+
+        (class != 0).then_some(Self {
+            class,
+            subject,
+            detail,
+        })
+    }
+}
+
+impl std::fmt::Display for SmtpEnhancedStatusCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.class, self.subject, self.detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reply(code: u16) -> SmtpReply {
+        SmtpReply {
+            code,
+            enhanced_status_code: None,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn classifies_reply_codes() {
+        for code in [421, 450, 451, 452] {
+            assert!(reply(code).is_transient(), "{code} should be transient");
+            assert!(
+                !reply(code).is_permanent(),
+                "{code} should not be permanent"
+            );
+        }
+
+        for code in [500, 530, 550, 552, 554] {
+            assert!(reply(code).is_permanent(), "{code} should be permanent");
+            assert!(
+                !reply(code).is_transient(),
+                "{code} should not be transient"
+            );
+        }
+
+        for code in [250, 354] {
+            assert!(!reply(code).is_transient());
+            assert!(!reply(code).is_permanent());
+        }
+    }
+
+    #[test]
+    fn extracts_reply_from_unexpected_reply_error() {
+        let error = mail_send::Error::UnexpectedReply(smtp_proto::Response {
+            code: 550,
+            esc: [5, 1, 1],
+            message: "mailbox unavailable".to_string(),
+        });
+
+        let reply = SmtpReply::from_error(&error).unwrap();
+
+        assert_eq!(reply.code, 550);
+        assert_eq!(
+            reply.enhanced_status_code,
+            Some(SmtpEnhancedStatusCode {
+                class: 5,
+                subject: 1,
+                detail: 1
+            })
+        );
+        assert!(reply.is_permanent());
+        assert_eq!(reply.to_string(), "550 5.1.1 mailbox unavailable");
+    }
+
+    #[test]
+    fn omits_absent_enhanced_status_code() {
+        let error = mail_send::Error::UnexpectedReply(smtp_proto::Response {
+            code: 451,
+            esc: [0, 0, 0],
+            message: "try again later".to_string(),
+        });
+
+        let reply = SmtpReply::from_error(&error).unwrap();
+
+        assert_eq!(reply.enhanced_status_code, None);
+        assert!(reply.is_transient());
+        assert_eq!(reply.to_string(), "451 try again later");
+    }
+
+    #[test]
+    fn ignores_errors_without_reply() {
+        assert_eq!(SmtpReply::from_error(&mail_send::Error::Timeout), None);
+    }
+}
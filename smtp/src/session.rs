@@ -0,0 +1,318 @@
+//! Establishing authenticated SMTP sessions for the [`SmtpMailer`].
+
+use std::io;
+
+use tokio::net::TcpStream;
+
+#[cfg(feature = "tracing")]
+use tracing::{debug, error, warn};
+
+use async_mailer_core::mail_send::{self, smtp::AssertReply, Credentials, SmtpClient};
+use smtp_proto::{EhloResponse, EXT_START_TLS};
+
+use crate::oauth2::OAuth2Authentication;
+use crate::pool::{PooledSession, SmtpSession};
+use crate::{SmtpAuthMechanism, SmtpMailer, SmtpMailerError, SmtpTlsMode};
+
+impl SmtpMailer {
+    /// Establish a new SMTP session, secure it according to the configured [`SmtpTlsMode`],
+    /// and authenticate if credentials or an OAuth2 token source are configured.
+    ///
+    /// Returns the session and the server's `EHLO` capabilities.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpMailerError::MissingStartTls`] error if STARTTLS is required but not offered by the server.
+    ///
+    /// Returns an [`SmtpMailerError::AccessToken`] error if an OAuth2 access token cannot be retrieved.
+    ///
+    /// Returns an [`SmtpMailerError::Connect`] error if connecting, the TLS handshake, `EHLO` or `AUTH` fail,
+    /// or if the session is not established within the configured timeout.
+    pub(crate) async fn connect(
+        &self,
+    ) -> Result<(SmtpSession, EhloResponse<String>), SmtpMailerError> {
+        let session = tokio::time::timeout(self.inner.timeout, async {
+            let (mut client, capabilities) =
+                self.establish_session()
+                    .await
+                    .map_err(|error| match error {
+                        mail_send::Error::MissingStartTls => SmtpMailerError::MissingStartTls,
+                        error => SmtpMailerError::Connect(error),
+                    })?;
+
+            if let Some(credentials) = &self.inner.credentials {
+                self.authenticate(&mut client, credentials, &capabilities)
+                    .await
+                    .map_err(SmtpMailerError::Connect)?;
+            } else if let Some(oauth2) = &self.oauth2 {
+                self.authenticate_oauth2(&mut client, oauth2, &capabilities)
+                    .await?;
+            }
+
+            Ok((client, capabilities))
+        })
+        .await;
+
+        session.unwrap_or(Err(SmtpMailerError::Connect(mail_send::Error::Timeout)))
+    }
+
+    /// Establish a new SMTP session for the pool, logging connection failures.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`SmtpMailer::connect`].
+    pub(crate) async fn connect_pooled(&self) -> Result<PooledSession, SmtpMailerError> {
+        let connection = self.connect().await;
+
+        #[cfg(feature = "tracing")]
+        if let Err(error) = &connection {
+            error!(?error, "Failed to connect to SMTP host");
+        }
+
+        let (client, capabilities) = connection?;
+
+        Ok(PooledSession::new(client, capabilities))
+    }
+
+    /// Connect to the SMTP server and secure the session according to the configured [`SmtpTlsMode`].
+    ///
+    /// Returns the session and the server's `EHLO` capabilities.
+    async fn establish_session(
+        &self,
+    ) -> Result<(SmtpSession, EhloResponse<String>), mail_send::Error> {
+        let mut client = self.connect_tcp().await?;
+
+        let mut client: SmtpSession = match self.tls_mode {
+            SmtpTlsMode::Implicit => {
+                let mut client = client
+                    .into_tls(&self.inner.tls_connector, &self.inner.tls_hostname)
+                    .await?;
+
+                // Read greeting
+                client.read().await?.assert_positive_completion()?;
+
+                boxed(client)
+            }
+            SmtpTlsMode::StartTls | SmtpTlsMode::StartTlsOpportunistic => {
+                // Read greeting
+                client.read().await?.assert_positive_completion()?;
+
+                let capabilities = client.ehlo(&self.inner.local_host).await?;
+
+                if capabilities.has_capability(EXT_START_TLS) {
+                    boxed(
+                        client
+                            .start_tls(&self.inner.tls_connector, &self.inner.tls_hostname)
+                            .await?,
+                    )
+                } else if self.tls_mode == SmtpTlsMode::StartTls {
+                    return Err(mail_send::Error::MissingStartTls);
+                } else {
+                    #[cfg(feature = "tracing")]
+                    warn!("SMTP host does not offer STARTTLS; continuing in plaintext");
+
+                    boxed(client)
+                }
+            }
+            SmtpTlsMode::Plaintext => {
+                // Read greeting
+                client.read().await?.assert_positive_completion()?;
+
+                boxed(client)
+            }
+        };
+
+        let capabilities = client.ehlo(&self.inner.local_host).await?;
+
+        Ok((client, capabilities))
+    }
+
+    /// Open the TCP connection to the SMTP server,
+    /// binding to the configured local IP address, if any.
+    ///
+    /// With a local IP address, only server addresses of the same address family are tried, in order.
+    async fn connect_tcp(&self) -> Result<SmtpClient<TcpStream>, mail_send::Error> {
+        let Some(local_ip) = self.inner.local_ip else {
+            return Ok(SmtpClient {
+                stream: TcpStream::connect(&self.inner.addr).await?,
+                timeout: self.inner.timeout,
+            });
+        };
+
+        let mut last_error = None;
+
+        for server_address in tokio::net::lookup_host(&self.inner.addr)
+            .await?
+            .filter(|server_address| server_address.is_ipv4() == local_ip.is_ipv4())
+        {
+            match SmtpClient::connect_using(local_ip, server_address, self.inner.timeout).await {
+                Ok(client) => return Ok(client),
+                Err(error) => last_error = Some(error),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::AddrNotAvailable,
+                "SMTP host has no address of the local IP address's address family",
+            )
+            .into()
+        }))
+    }
+
+    /// Authenticate the session, trying the preferred authentication mechanisms in order.
+    ///
+    /// Without preferred mechanisms, the most secure mechanism supported by both client and server is chosen.
+    async fn authenticate(
+        &self,
+        client: &mut SmtpSession,
+        credentials: &Credentials<String>,
+        capabilities: &EhloResponse<String>,
+    ) -> Result<(), mail_send::Error> {
+        if self.auth_mechanisms.is_empty() {
+            client.authenticate(credentials, capabilities).await?;
+            return Ok(());
+        }
+
+        for mechanism in &self.auth_mechanisms {
+            let mechanism_bit = mechanism.to_smtp_proto();
+            if capabilities.auth_mechanisms & mechanism_bit == 0 {
+                continue;
+            }
+
+            // Restrict the advertised mechanisms, so the client only tries the preferred mechanism.
+            let restricted = EhloResponse {
+                auth_mechanisms: mechanism_bit,
+                ..capabilities.clone()
+            };
+
+            match client.authenticate(credentials, &restricted).await {
+                Ok(_) => return Ok(()),
+                // The mechanism cannot be used with the configured credentials.
+                Err(mail_send::Error::UnsupportedAuthMechanism) => {
+                    #[cfg(feature = "tracing")]
+                    debug!(?mechanism, "Skipping unsupported authentication mechanism");
+                }
+                Err(error) => return Err(error),
+            }
+        }
+
+        Err(mail_send::Error::UnsupportedAuthMechanism)
+    }
+
+    /// Authenticate the session with an OAuth2 access token, using the first preferred
+    /// OAuth2 mechanism offered by the server. Without preferred mechanisms, `XOAUTH2` is preferred over `OAUTHBEARER`.
+    ///
+    /// If the server rejects the cached access token with `535`,
+    /// a new access token is retrieved and authentication is retried once.
+    async fn authenticate_oauth2(
+        &self,
+        client: &mut SmtpSession,
+        oauth2: &OAuth2Authentication,
+        capabilities: &EhloResponse<String>,
+    ) -> Result<(), SmtpMailerError> {
+        let preferred_mechanisms: &[SmtpAuthMechanism] = if self.auth_mechanisms.is_empty() {
+            &[SmtpAuthMechanism::XOAuth2, SmtpAuthMechanism::OAuthBearer]
+        } else {
+            &self.auth_mechanisms
+        };
+
+        let mechanism = preferred_mechanisms
+            .iter()
+            .copied()
+            .find(|mechanism| {
+                matches!(
+                    mechanism,
+                    SmtpAuthMechanism::XOAuth2 | SmtpAuthMechanism::OAuthBearer
+                ) && capabilities.auth_mechanisms & mechanism.to_smtp_proto() != 0
+            })
+            .ok_or(SmtpMailerError::Connect(
+                mail_send::Error::UnsupportedAuthMechanism,
+            ))?;
+
+        // Restrict the advertised mechanisms, so the client only tries the selected mechanism.
+        let restricted = EhloResponse {
+            auth_mechanisms: mechanism.to_smtp_proto(),
+            ..capabilities.clone()
+        };
+
+        let mut refresh = false;
+
+        loop {
+            let access_token = oauth2
+                .access_token(refresh)
+                .await
+                .map_err(SmtpMailerError::AccessToken)?;
+
+            let Some(credentials) = oauth2.credentials(mechanism, &access_token) else {
+                return Err(SmtpMailerError::Connect(
+                    mail_send::Error::UnsupportedAuthMechanism,
+                ));
+            };
+
+            match client.authenticate(&credentials, &restricted).await {
+                Ok(_) => return Ok(()),
+                Err(mail_send::Error::AuthenticationFailed(response))
+                    if response.code == 535 && !refresh =>
+                {
+                    #[cfg(feature = "tracing")]
+                    debug!(
+                        ?mechanism,
+                        "SMTP server rejected the access token; retrieving a new access token..."
+                    );
+
+                    refresh = true;
+                }
+                Err(error) => return Err(SmtpMailerError::Connect(error)),
+            }
+        }
+    }
+}
+
+/// Erase the stream type of an SMTP client.
+fn boxed<T: crate::pool::SmtpStream + 'static>(client: SmtpClient<T>) -> SmtpSession {
+    SmtpClient {
+        stream: Box::new(client.stream),
+        timeout: client.timeout,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn connects_from_local_ip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mailer = SmtpMailer::builder("127.0.0.1", port)
+            .local_ip(Ipv4Addr::LOCALHOST)
+            .build()
+            .unwrap();
+
+        let (client, accepted) = tokio::join!(mailer.connect_tcp(), listener.accept());
+
+        let client = client.unwrap();
+        let (_, peer_address) = accepted.unwrap();
+        assert_eq!(peer_address.ip(), IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(client.stream.local_addr().unwrap(), peer_address);
+    }
+
+    #[tokio::test]
+    async fn skips_server_addresses_of_other_address_family() {
+        let mailer = SmtpMailer::builder("127.0.0.1", 25)
+            .local_ip(Ipv6Addr::LOCALHOST)
+            .build()
+            .unwrap();
+
+        let Err(mail_send::Error::Io(error)) = mailer.connect_tcp().await else {
+            panic!("expected I/O error");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::AddrNotAvailable);
+    }
+}
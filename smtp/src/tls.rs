@@ -0,0 +1,376 @@
+//! TLS configuration of the [`SmtpMailer`](crate::SmtpMailer):
+//! additional trusted root certificates, certificate pinning and client certificates.
+
+use std::sync::Arc;
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::{self, PemObject};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use rustls_platform_verifier::Verifier;
+use sha2::{Digest, Sha256};
+use tokio_rustls::TlsConnector;
+
+use crate::SmtpInvalidCertsPolicy;
+
+/// Error returned by [`SmtpCertificate::from_pem`] and [`SmtpClientCertificate::from_pem`]
+/// if a certificate or private key cannot be parsed.
+#[derive(Debug, thiserror::Error)]
+pub enum SmtpCertificateError {
+    /// Failed to parse PEM-encoded X.509 certificate.
+    #[error("failed to parse PEM-encoded X.509 certificate: {0}")]
+    ParseCertificate(pem::Error),
+
+    /// Failed to parse PEM-encoded private key.
+    #[error("failed to parse PEM-encoded private key: {0}")]
+    ParsePrivateKey(pem::Error),
+}
+
+/// An X.509 certificate, passed to [`SmtpMailerBuilder::root_certificate`](crate::SmtpMailerBuilder::root_certificate)
+/// to be trusted as root certificate authority.
+#[derive(Clone, Debug)]
+pub struct SmtpCertificate(CertificateDer<'static>);
+
+impl SmtpCertificate {
+    /// Parse the first certificate of a PEM-encoded X.509 certificate file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpCertificateError::ParseCertificate`] error
+    /// if the PEM file cannot be parsed or contains no certificate.
+    pub fn from_pem(pem: &[u8]) -> Result<Self, SmtpCertificateError> {
+        CertificateDer::from_pem_slice(pem)
+            .map(Self)
+            .map_err(SmtpCertificateError::ParseCertificate)
+    }
+
+    /// Wrap a DER-encoded X.509 certificate.
+    pub fn from_der(der: Vec<u8>) -> Self {
+        Self(CertificateDer::from(der))
+    }
+}
+
+/// A client certificate chain and its private key, passed to
+/// [`SmtpMailerBuilder::client_certificate`](crate::SmtpMailerBuilder::client_certificate)
+/// to authenticate with SMTP relays requiring mutual TLS.
+#[derive(Clone)]
+pub struct SmtpClientCertificate {
+    certificate_chain: Vec<CertificateDer<'static>>,
+    private_key: Arc<PrivateKeyDer<'static>>,
+}
+
+impl SmtpClientCertificate {
+    /// Parse a PEM-encoded X.509 client certificate chain, leaf certificate first,
+    /// and its PEM-encoded PKCS#1, PKCS#8 or SEC1 private key.
+    ///
+    /// # Errors
+    ///
+    /// Returns an [`SmtpCertificateError::ParseCertificate`] error
+    /// if the certificate chain cannot be parsed or contains no certificate.
+    ///
+    /// Returns an [`SmtpCertificateError::ParsePrivateKey`] error
+    /// if the private key cannot be parsed.
+    pub fn from_pem(
+        certificate_chain_pem: &[u8],
+        private_key_pem: &[u8],
+    ) -> Result<Self, SmtpCertificateError> {
+        let certificate_chain = CertificateDer::pem_slice_iter(certificate_chain_pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(SmtpCertificateError::ParseCertificate)?;
+
+        if certificate_chain.is_empty() {
+            return Err(SmtpCertificateError::ParseCertificate(
+                pem::Error::NoItemsFound,
+            ));
+        }
+
+        let private_key = PrivateKeyDer::from_pem_slice(private_key_pem)
+            .map_err(SmtpCertificateError::ParsePrivateKey)?;
+
+        Ok(Self {
+            certificate_chain,
+            private_key: Arc::new(private_key),
+        })
+    }
+}
+
+impl std::fmt::Debug for SmtpClientCertificate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SmtpClientCertificate")
+            .field("certificate_chain", &self.certificate_chain)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Custom TLS configuration, replacing the default `mail-send` TLS connector.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TlsConfig {
+    pub(crate) root_certificates: Vec<SmtpCertificate>,
+    pub(crate) spki_pins: Vec<[u8; 32]>,
+    pub(crate) client_certificate: Option<SmtpClientCertificate>,
+}
+
+impl TlsConfig {
+    /// Whether the default `mail-send` TLS connector must be replaced.
+    pub(crate) fn is_custom(&self) -> bool {
+        !self.root_certificates.is_empty()
+            || !self.spki_pins.is_empty()
+            || self.client_certificate.is_some()
+    }
+
+    /// Build a TLS connector, verifying server certificates against the platform's root certificates
+    /// and the additional root certificates, then checking certificate pins.
+    pub(crate) fn build_connector(
+        &self,
+        invalid_certs: &SmtpInvalidCertsPolicy,
+    ) -> Result<TlsConnector, String> {
+        let builder = ClientConfig::builder();
+        let crypto_provider = builder.crypto_provider().clone();
+
+        let mut verifier: Arc<dyn ServerCertVerifier> = match invalid_certs {
+            SmtpInvalidCertsPolicy::Deny => Arc::new(
+                Verifier::new_with_extra_roots(
+                    self.root_certificates
+                        .iter()
+                        .map(|certificate| certificate.0.clone()),
+                    crypto_provider.clone(),
+                )
+                .map_err(|error| format!("failed to build certificate verifier: {error}"))?,
+            ),
+            SmtpInvalidCertsPolicy::Allow => Arc::new(AllowInvalidCerts(crypto_provider)),
+        };
+
+        if !self.spki_pins.is_empty() {
+            verifier = Arc::new(SpkiPinningVerifier {
+                inner: verifier,
+                spki_pins: self.spki_pins.clone(),
+            });
+        }
+
+        let builder = builder
+            .dangerous()
+            .with_custom_certificate_verifier(verifier);
+
+        let config = match &self.client_certificate {
+            Some(client_certificate) => builder
+                .with_client_auth_cert(
+                    client_certificate.certificate_chain.clone(),
+                    client_certificate.private_key.clone_key(),
+                )
+                .map_err(|error| format!("invalid client certificate: {error}"))?,
+            None => builder.with_no_client_auth(),
+        };
+
+        Ok(TlsConnector::from(Arc::new(config)))
+    }
+}
+
+/// Accepts any server certificate, while still verifying handshake signatures.
+///
+/// Used with [`SmtpInvalidCertsPolicy::Allow`].
+#[derive(Debug)]
+struct AllowInvalidCerts(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AllowInvalidCerts {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+/// Requires the SHA-256 hash of the subject public key info (SPKI) of a certificate
+/// in the server's certificate chain to match one of the pins,
+/// after the inner verifier accepted the certificate chain.
+#[derive(Debug)]
+struct SpkiPinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    spki_pins: Vec<[u8; 32]>,
+}
+
+impl ServerCertVerifier for SpkiPinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let is_pinned = std::iter::once(end_entity)
+            .chain(intermediates)
+            .filter_map(|certificate| webpki::EndEntityCert::try_from(certificate).ok())
+            .any(|certificate| {
+                let spki_hash: [u8; 32] =
+                    Sha256::digest(certificate.subject_public_key_info()).into();
+                self.spki_pins.contains(&spki_hash)
+            });
+
+        if is_pinned {
+            Ok(verified)
+        } else {
+            Err(rustls::Error::General(
+                "no certificate in the chain matches the pinned public keys".into(),
+            ))
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Self-signed certificate for `leaf.example.com`.
+    const LEAF_PEM: &[u8] = b"-----BEGIN CERTIFICATE-----
+MIIBjTCCATOgAwIBAgIUZizdtb6e+F5NdzWyExvQ6+ZI7u0wCgYIKoZIzj0EAwIw
+GzEZMBcGA1UEAwwQbGVhZi5leGFtcGxlLmNvbTAgFw0yNjEwMTYxOTM2NDFaGA8y
+MTI2MDkyMjE5MzY0MVowGzEZMBcGA1UEAwwQbGVhZi5leGFtcGxlLmNvbTBZMBMG
+ByqGSM49AgEGCCqGSM49AwEHA0IABKL7PEJSR6ouQ4j2hVSJAxmKEZyoTDiawXaN
+UqPER3HUbWS49zrioH/x3X/IU+5oc+sAEzABqwZeHC98/hbkMMujUzBRMB0GA1Ud
+DgQWBBRJx1z1oUguM7eWfJeKKpYut4Q8WzAfBgNVHSMEGDAWgBRJx1z1oUguM7eW
+fJeKKpYut4Q8WzAPBgNVHRMBAf8EBTADAQH/MAoGCCqGSM49BAMCA0gAMEUCIEJG
+doysxkLLqJyhjF5ncalDA3+TrymwO/AUlD/5HnK5AiEAm9tQqxzSo/XDp1WwOQZw
+cNTyvXwZ7cXFejj9l5FMniI=
+-----END CERTIFICATE-----
+";
+
+    /// SPKI SHA-256 hash of [`LEAF_PEM`], as computed by `openssl`.
+    const LEAF_SPKI_SHA256: &str =
+        "37ddd8b6f2d9bbaf6dff54f572d90b850eaced932a4326602969bb715d121ba4";
+
+    /// Self-signed certificate for `ca.example.com`.
+    const CA_PEM: &[u8] = b"-----BEGIN CERTIFICATE-----
+MIIBijCCAS+gAwIBAgIULobXcLtB6s11uMjU3yQgcaRmT6kwCgYIKoZIzj0EAwIw
+GTEXMBUGA1UEAwwOY2EuZXhhbXBsZS5jb20wIBcNMjYxMDE2MTkzNjQxWhgPMjEy
+NjA5MjIxOTM2NDFaMBkxFzAVBgNVBAMMDmNhLmV4YW1wbGUuY29tMFkwEwYHKoZI
+zj0CAQYIKoZIzj0DAQcDQgAErhn1K3BB8+lnKRUObth1oMLfof14WHzy4kVZB8hz
+IWmu4XcQ0gXJuBJN6YB5YHjyQ+xdCXIFqd3f5nUxlT5XS6NTMFEwHQYDVR0OBBYE
+FNEbHV+kFA0ADxwQ9uCNj+Hk5pgEMB8GA1UdIwQYMBaAFNEbHV+kFA0ADxwQ9uCN
+j+Hk5pgEMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSQAwRgIhAOGN66pv
+nb9+36sxGyn/oB8/MUCWGcKRkBShh+HdB7BWAiEAgdq3fmt8MpS5whVDQvcU2Pv8
+CXw+bCxCbmAfbCUoen0=
+-----END CERTIFICATE-----
+";
+
+    /// SPKI SHA-256 hash of [`CA_PEM`], as computed by `openssl`.
+    const CA_SPKI_SHA256: &str = "965228b2679e401e573060b40773ea77a3a3d7bb4beb2560001916be7db30cfb";
+
+    fn spki_pin(hex: &str) -> [u8; 32] {
+        let mut pin = [0; 32];
+        for (byte, digits) in pin.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(digits).unwrap(), 16).unwrap();
+        }
+        pin
+    }
+
+    fn verify(
+        spki_pins: &[&str],
+        end_entity: &[u8],
+        intermediates: &[&[u8]],
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verifier = SpkiPinningVerifier {
+            inner: Arc::new(AllowInvalidCerts(Arc::new(
+                rustls::crypto::aws_lc_rs::default_provider(),
+            ))),
+            spki_pins: spki_pins.iter().map(|hex| spki_pin(hex)).collect(),
+        };
+
+        let end_entity = SmtpCertificate::from_pem(end_entity).unwrap().0;
+        let intermediates: Vec<_> = intermediates
+            .iter()
+            .map(|pem| SmtpCertificate::from_pem(pem).unwrap().0)
+            .collect();
+
+        verifier.verify_server_cert(
+            &end_entity,
+            &intermediates,
+            &ServerName::try_from("leaf.example.com").unwrap(),
+            &[],
+            UnixTime::now(),
+        )
+    }
+
+    #[test]
+    fn accepts_pinned_end_entity_certificate() {
+        assert!(verify(&[CA_SPKI_SHA256, LEAF_SPKI_SHA256], LEAF_PEM, &[]).is_ok());
+    }
+
+    #[test]
+    fn accepts_pinned_intermediate_certificate() {
+        assert!(verify(&[CA_SPKI_SHA256], LEAF_PEM, &[CA_PEM]).is_ok());
+    }
+
+    #[test]
+    fn rejects_chain_without_pinned_certificate() {
+        assert!(verify(&[CA_SPKI_SHA256], LEAF_PEM, &[]).is_err());
+        assert!(verify(&[LEAF_SPKI_SHA256], CA_PEM, &[CA_PEM]).is_err());
+    }
+}
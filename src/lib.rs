@@ -129,14 +129,14 @@
 //!   All relevant functions are instrumented.
 //! - `clap`: Implement [`clap::ValueEnum`](https://docs.rs/clap/latest/clap/trait.ValueEnum.html) for [`SmtpInvalidCertsPolicy`].
 //!   This allows for easily configured CLI options like `--invalid-certs <allow|deny>`.
+//! - `outlook-certificate`: Enable `OutlookCredentials::Certificate` on the [`OutlookMailer`],
+//!   authenticating with client assertion JWTs signed by an X.509 certificate private key.
+//! - `smtp-dkim`: Enable DKIM signing of messages sent by the [`SmtpMailer`], with RSA-SHA256 or Ed25519-SHA256 keys.
 //!
 //! Default: `outlook`, `smtp`, `tracing`.
 //!
 //! ## Roadmap
 //!
-//! - DKIM support is planned to be implemented on the [`SmtpMailer`].
-//! - Access token auto-refresh is planned to be implemented on the [`OutlookMailer`].
-//!
 //! Further mailer implementations are possible.
 //! Please open an issue and ideally provide a pull request to add your alternative mailer implementation!
 //!